@@ -0,0 +1,142 @@
+use std::{fmt, str::FromStr};
+
+use bdk::bitcoin::{util::address::Payload, Address, Network};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressError {
+    Invalid(String),
+    WrongNetwork { expected: Network, found: Network },
+    /// A witness version or program we can't pay to safely
+    UnsupportedScript(String),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::Invalid(err) => write!(f, "Invalid address: {}", err),
+            AddressError::WrongNetwork { expected, found } => {
+                write!(f, "This is a {} address but the wallet is on {}", found, expected)
+            }
+            AddressError::UnsupportedScript(script) => write!(f, "Can't send to {} addresses", script),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// Parses an address and checks it's for `network`. Regtest shares testnet's base58
+/// prefixes, so those parse as testnet and are accepted on regtest too.
+pub fn parse_address(address: &str, network: Network) -> Result<Address, AddressError> {
+    let address =
+        Address::from_str(address.trim()).map_err(|err| AddressError::Invalid(err.to_string()))?;
+    let base58 = matches!(address.payload, Payload::PubkeyHash(_) | Payload::ScriptHash(_));
+    let matches = address.network == network
+        || (base58 && address.network == Network::Testnet && network == Network::Regtest);
+    if !matches {
+        return Err(AddressError::WrongNetwork {
+            expected: network,
+            found: address.network,
+        });
+    }
+    // Later versions need bech32m, which the pinned rust-bitcoin doesn't check, so paying
+    // them could burn the coins. It already rejects v0 programs that aren't 20 or 32 bytes.
+    if let Payload::WitnessProgram { version, .. } = &address.payload {
+        if version.to_u8() != 0 {
+            return Err(AddressError::UnsupportedScript(format!("segwit v{}", version.to_u8())));
+        }
+    }
+    Ok(address)
+}
+
+/// The script type an address pays to, e.g. `P2WPKH`.
+pub fn script_type(address: &Address) -> &'static str {
+    match &address.payload {
+        Payload::PubkeyHash(_) => "P2PKH",
+        Payload::ScriptHash(_) => "P2SH",
+        Payload::WitnessProgram { program, .. } if program.len() == 20 => "P2WPKH",
+        Payload::WitnessProgram { .. } => "P2WSH",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Addresses paying to the hash 79b000887626b294a914501a4cd226b58b235983, or for P2WSH and
+    // segwit v1 to the 32 bytes 00 01 .. 1f
+    const REGTEST_P2WPKH: &str = "bcrt1q0xcqpzrky6eff2g52qdye53xkk9jxkvrl4xfg5";
+    const REGTEST_P2WSH: &str = "bcrt1qqqqsyqcyq5rqwzqfpg9scrgwpugpzysnzs23v9ccrydpk8qarc0scjhmyt";
+    const MAINNET_P2WPKH: &str = "bc1q0xcqpzrky6eff2g52qdye53xkk9jxkvrh6yhyw";
+    const MAINNET_P2PKH: &str = "1C6Rc3w25VHud3dLDamutaqfKWqhrLRTaD";
+    const TESTNET_P2WPKH: &str = "tb1q0xcqpzrky6eff2g52qdye53xkk9jxkvraulyla";
+    const TESTNET_P2PKH: &str = "mrcNu71ztWjAQA6ww9kHiW3zBWSQidHXTQ";
+    const TESTNET_P2SH: &str = "2N4LebLMVEr7duzxK1p4NwABrgPLb9sm9uE";
+    // Segwit v1 with a bech32 checksum, which is what the pinned rust-bitcoin expects
+    const REGTEST_V1: &str = "bcrt1pqqqsyqcyq5rqwzqfpg9scrgwpugpzysnzs23v9ccrydpk8qarc0s8e87e4";
+
+    #[test]
+    fn rejects_other_networks() {
+        for address in [MAINNET_P2WPKH, MAINNET_P2PKH].iter() {
+            assert_eq!(
+                parse_address(address, Network::Regtest),
+                Err(AddressError::WrongNetwork {
+                    expected: Network::Regtest,
+                    found: Network::Bitcoin,
+                })
+            );
+        }
+        assert_eq!(
+            parse_address(REGTEST_P2WPKH, Network::Bitcoin),
+            Err(AddressError::WrongNetwork {
+                expected: Network::Bitcoin,
+                found: Network::Regtest,
+            })
+        );
+    }
+
+    #[test]
+    fn testnet_base58_is_accepted_on_regtest() {
+        assert!(parse_address(TESTNET_P2PKH, Network::Regtest).is_ok());
+        assert!(parse_address(TESTNET_P2SH, Network::Regtest).is_ok());
+        assert!(parse_address(TESTNET_P2PKH, Network::Testnet).is_ok());
+
+        // tb1 and bcrt1 are different prefixes, so only base58 is shared
+        assert_eq!(
+            parse_address(TESTNET_P2WPKH, Network::Regtest),
+            Err(AddressError::WrongNetwork {
+                expected: Network::Regtest,
+                found: Network::Testnet,
+            })
+        );
+        assert!(parse_address(TESTNET_P2WPKH, Network::Testnet).is_ok());
+    }
+
+    #[test]
+    fn rejects_segwit_v1() {
+        assert_eq!(
+            parse_address(REGTEST_V1, Network::Regtest),
+            Err(AddressError::UnsupportedScript("segwit v1".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let tampered = format!("{}4", &REGTEST_P2WPKH[..REGTEST_P2WPKH.len() - 1]);
+        assert!(matches!(parse_address(&tampered, Network::Regtest), Err(AddressError::Invalid(_))));
+        assert!(matches!(parse_address("not an address", Network::Regtest), Err(AddressError::Invalid(_))));
+    }
+
+    #[test]
+    fn names_script_types() {
+        let cases = [
+            (TESTNET_P2PKH, "P2PKH"),
+            (TESTNET_P2SH, "P2SH"),
+            (REGTEST_P2WPKH, "P2WPKH"),
+            (REGTEST_P2WSH, "P2WSH"),
+        ];
+        for (address, expected) in cases.iter() {
+            let address = parse_address(address, Network::Regtest).unwrap();
+            assert_eq!(script_type(&address), *expected);
+        }
+    }
+}
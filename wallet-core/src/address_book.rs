@@ -0,0 +1,96 @@
+//! Saved recipients, kept in the wallet registry.
+
+use std::fmt;
+
+use bdk::bitcoin::Network;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::bip21::{PaymentUri, UriError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContactError {
+    EmptyName,
+    Address(UriError),
+}
+
+impl fmt::Display for ContactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContactError::EmptyName => write!(f, "Give the contact a name"),
+            ContactError::Address(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ContactError {}
+
+impl From<UriError> for ContactError {
+    fn from(err: UriError) -> Self {
+        ContactError::Address(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    /// A plain address or a `bitcoin:` URI
+    pub address: String,
+    #[serde(with = "network_name")]
+    pub network: Network,
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl Contact {
+    /// A contact whose address is valid on `network`.
+    pub fn new(name: &str, address: &str, network: Network, notes: &str) -> Result<Self, ContactError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ContactError::EmptyName);
+        }
+        let address = address.trim();
+        PaymentUri::parse(address, network)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            address: address.to_string(),
+            network,
+            notes: notes.trim().to_string(),
+        })
+    }
+
+    pub fn is_on(&self, network: Network) -> bool {
+        self.network == network
+    }
+}
+
+/// Networks are saved by name, `regtest` and so on, the same as the wallets in the registry.
+mod network_name {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(network: &Network, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(network)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Network, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_is_saved_by_name() {
+        let json = r#"{"name":"Alice","address":"bcrt1qexample","network":"regtest"}"#;
+        let contact: Contact = serde_json::from_str(json).unwrap();
+        assert!(contact.is_on(Network::Regtest));
+        assert!(!contact.is_on(Network::Testnet));
+        assert!(serde_json::to_string(&contact).unwrap().contains(r#""network":"regtest""#));
+
+        let json = r#"{"name":"Alice","address":"bcrt1qexample","network":"moonnet"}"#;
+        assert!(serde_json::from_str::<Contact>(json).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Single file wallet backups for moving a wallet to another machine.
+//!
+//! A backup holds no private keys, only what's needed to watch the wallet and find its
+//! signers again, but it still reveals the whole wallet history. So it's encrypted with a
+//! password the same way as the registry.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{descriptor::DescriptorError, registry::WalletEntry, SlapsWallet, Storage, StorageError};
+
+const BACKUP_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum BackupError {
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    Descriptor(DescriptorError),
+    Storage(StorageError),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Json(err) => write!(f, "Not a wallet backup: {}", err),
+            BackupError::UnsupportedVersion(version) => {
+                write!(f, "Backup version {} is newer than this app", version)
+            }
+            BackupError::Descriptor(err) => write!(f, "{}", err),
+            BackupError::Storage(err) => write!(f, "Couldn't decrypt the backup: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(err: serde_json::Error) -> Self {
+        BackupError::Json(err)
+    }
+}
+
+impl From<DescriptorError> for BackupError {
+    fn from(err: DescriptorError) -> Self {
+        BackupError::Descriptor(err)
+    }
+}
+
+impl From<StorageError> for BackupError {
+    fn from(err: StorageError) -> Self {
+        BackupError::Storage(err)
+    }
+}
+
+/// Descriptors, labels, network, signer fingerprints and multisig registrations of one wallet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalletBackup {
+    pub version: u32,
+    pub wallet: WalletEntry,
+}
+
+impl WalletBackup {
+    pub fn new(wallet: &SlapsWallet) -> Self {
+        Self {
+            version: BACKUP_VERSION,
+            wallet: wallet.to_entry(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Backups always serialize")
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, BackupError> {
+        let backup: Self = serde_json::from_str(contents)?;
+        if backup.version > BACKUP_VERSION {
+            return Err(BackupError::UnsupportedVersion(backup.version));
+        }
+        Ok(backup)
+    }
+
+    /// The backup's JSON encrypted with `password`.
+    pub fn encrypt(&self, password: &str) -> Result<Vec<u8>, BackupError> {
+        Ok(Storage::seal(password, self.to_json().as_bytes())?)
+    }
+
+    /// Reads an encrypted backup, or a plaintext one saved before backups were encrypted.
+    pub fn decrypt(bytes: &[u8], password: &str) -> Result<Self, BackupError> {
+        let contents = match Storage::unseal(password, bytes) {
+            Err(StorageError::Unencrypted) => bytes.to_vec(),
+            contents => contents?,
+        };
+        Self::from_json(&String::from_utf8_lossy(&contents))
+    }
+
+    /// Recreates the wallet. Its transactions come back on the next sync.
+    pub fn restore(&self) -> Result<SlapsWallet, BackupError> {
+        Ok(SlapsWallet::from_entry(&self.wallet)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup() -> WalletBackup {
+        let json = r#"{
+            "version": 1,
+            "wallet": {
+                "name": "Savings",
+                "descriptor": "wpkh([2de67592/84'/1'/0']tpubDFFgV6kiSbSwMDKhketWTo3hRuvdxCuK7Xzc4MW1joKTV9wGrp5Xbsvk79QhU3qNzkijKSvo18JzzdEfBx9QHWH3cqLYiQAKParawyXQH3k/0/*)",
+                "change_descriptor": "wpkh([2de67592/84'/1'/0']tpubDFFgV6kiSbSwMDKhketWTo3hRuvdxCuK7Xzc4MW1joKTV9wGrp5Xbsvk79QhU3qNzkijKSvo18JzzdEfBx9QHWH3cqLYiQAKParawyXQH3k/1/*)",
+                "network": "regtest",
+                "backend": "tcp://localhost:51401",
+                "fingerprints": ["2de67592"]
+            }
+        }"#;
+        WalletBackup::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let backup = backup();
+        let encrypted = backup.encrypt("hunter2").unwrap();
+        assert!(!String::from_utf8_lossy(&encrypted).contains("Savings"));
+        assert_eq!(WalletBackup::decrypt(&encrypted, "hunter2").unwrap(), backup);
+        assert!(matches!(
+            WalletBackup::decrypt(&encrypted, "hunter3"),
+            Err(BackupError::Storage(StorageError::WrongPassword))
+        ));
+    }
+
+    #[test]
+    fn reads_plaintext_backups() {
+        let backup = backup();
+        assert_eq!(WalletBackup::decrypt(backup.to_json().as_bytes(), "").unwrap(), backup);
+    }
+}
@@ -0,0 +1,208 @@
+//! BIP21 `bitcoin:` payment URIs.
+
+use std::fmt;
+
+use bdk::bitcoin::{Address, Network};
+
+use crate::address::{self, AddressError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UriError {
+    Address(AddressError),
+    InvalidAmount(String),
+    InvalidEncoding,
+    /// A `req-` parameter we don't understand, which BIP21 says makes the URI invalid
+    UnknownRequirement(String),
+}
+
+impl fmt::Display for UriError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UriError::Address(err) => write!(f, "{}", err),
+            UriError::InvalidAmount(amount) => write!(f, "Invalid amount: {}", amount),
+            UriError::InvalidEncoding => write!(f, "Payment URI is badly encoded"),
+            UriError::UnknownRequirement(param) => {
+                write!(f, "Payment URI requires {}, which isn't supported", param)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UriError {}
+
+impl From<AddressError> for UriError {
+    fn from(err: AddressError) -> Self {
+        UriError::Address(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymentUri {
+    pub address: Address,
+    /// In satoshis
+    pub amount: Option<u64>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+}
+
+impl PaymentUri {
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            amount: None,
+            label: None,
+            message: None,
+        }
+    }
+
+    /// Parses a `bitcoin:` URI, or a bare address, for `network`.
+    pub fn parse(uri: &str, network: Network) -> Result<Self, UriError> {
+        let uri = uri.trim();
+        let rest = match uri.get(..8) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("bitcoin:") => &uri[8..],
+            _ => uri,
+        };
+        let mut parts = rest.splitn(2, '?');
+        let address = address::parse_address(parts.next().unwrap_or(""), network)?;
+        let mut payment = Self::new(address);
+
+        for param in parts.next().unwrap_or("").split('&').filter(|p| !p.is_empty()) {
+            let mut key_value = param.splitn(2, '=');
+            let key = key_value.next().unwrap_or("");
+            let value = percent_decode(key_value.next().unwrap_or(""))?;
+            match key {
+                "amount" => {
+                    let amount = parse_btc(&value).ok_or_else(|| UriError::InvalidAmount(value.clone()))?;
+                    payment.amount = Some(amount);
+                }
+                "label" => payment.label = Some(value),
+                "message" => payment.message = Some(value),
+                key if key.starts_with("req-") => {
+                    return Err(UriError::UnknownRequirement(key.to_string()))
+                }
+                _ => (),
+            }
+        }
+        Ok(payment)
+    }
+}
+
+impl fmt::Display for PaymentUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bitcoin:{}", self.address)?;
+        let mut params = vec![];
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", format_btc(amount)));
+        }
+        if let Some(label) = &self.label {
+            params.push(format!("label={}", percent_encode(label)));
+        }
+        if let Some(message) = &self.message {
+            params.push(format!("message={}", percent_encode(message)));
+        }
+        if !params.is_empty() {
+            write!(f, "?{}", params.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a decimal BTC amount like `0.0015` into satoshis without going through floats.
+pub fn parse_btc(amount: &str) -> Option<u64> {
+    let mut parts = amount.splitn(2, '.');
+    let whole = parts.next()?;
+    let fraction = parts.next().unwrap_or("");
+    if (whole.is_empty() && fraction.is_empty())
+        || fraction.len() > 8
+        || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let fraction: u64 = format!("{:0<8}", fraction).parse().ok()?;
+    whole.checked_mul(100_000_000)?.checked_add(fraction)
+}
+
+/// Formats satoshis as BTC without trailing zeros, e.g. `0.0015`.
+pub fn format_btc(sats: u64) -> String {
+    let formatted = format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn percent_decode(s: &str) -> Result<String, UriError> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3).ok_or(UriError::InvalidEncoding)?;
+            // from_str_radix would take a sign, as in `%+1`
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(UriError::InvalidEncoding);
+            }
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| UriError::InvalidEncoding)?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| UriError::InvalidEncoding)
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "bcrt1q0xcqpzrky6eff2g52qdye53xkk9jxkvrl4xfg5";
+
+    fn parse(params: &str) -> Result<PaymentUri, UriError> {
+        PaymentUri::parse(&format!("bitcoin:{}?{}", ADDRESS, params), Network::Regtest)
+    }
+
+    #[test]
+    fn parses_amounts() {
+        assert_eq!(parse("amount=.5").unwrap().amount, Some(50_000_000));
+        assert_eq!(parse("amount=1.").unwrap().amount, Some(100_000_000));
+        assert_eq!(parse("amount=0.00000001").unwrap().amount, Some(1));
+        assert_eq!(parse("amount=0.000000001"), Err(UriError::InvalidAmount("0.000000001".into())));
+        assert_eq!(parse("amount=."), Err(UriError::InvalidAmount(".".into())));
+        assert_eq!(parse("amount=-1"), Err(UriError::InvalidAmount("-1".into())));
+    }
+
+    #[test]
+    fn rejects_unknown_requirements() {
+        assert_eq!(
+            parse("req-somethingyoudontunderstand=50"),
+            Err(UriError::UnknownRequirement("req-somethingyoudontunderstand".into()))
+        );
+        assert!(parse("somethingyoudontunderstand=50").is_ok());
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(parse("label=Luke-Jr%20%C3%A9").unwrap().label.as_deref(), Some("Luke-Jr \u{e9}"));
+        assert_eq!(parse("label=%+1"), Err(UriError::InvalidEncoding));
+        assert_eq!(parse("label=%2"), Err(UriError::InvalidEncoding));
+        assert_eq!(parse("label=%zz"), Err(UriError::InvalidEncoding));
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut payment = PaymentUri::parse(ADDRESS, Network::Regtest).unwrap();
+        payment.amount = Some(150_000);
+        payment.label = Some("Rent & bills".into());
+        let uri = payment.to_string();
+        assert_eq!(uri, format!("bitcoin:{}?amount=0.0015&label=Rent%20%26%20bills", ADDRESS));
+        assert_eq!(PaymentUri::parse(&uri, Network::Regtest).unwrap(), payment);
+    }
+}
@@ -0,0 +1,417 @@
+//! Bitcoin Secure Multisig Setup (BIP129) coordinator.
+//!
+//! The coordinator hands out a token, collects a signed key record from each signer and
+//! returns the descriptor record every cosigner imports to finish the setup.
+
+use std::{fmt, str::FromStr};
+
+use aes::Aes256;
+use bdk::bitcoin::{
+    consensus::encode::VarInt,
+    consensus::serialize,
+    hashes::{hex::FromHex, hex::ToHex, hmac, sha256, sha256d, sha512, Hash, HashEngine},
+    secp256k1::{Message, Secp256k1, Signature},
+    util::bip32::Fingerprint,
+    Network,
+};
+use bdk::{database::MemoryDatabase, descriptor::get_checksum, Wallet};
+use ctr::cipher::{NewCipher, StreamCipher};
+use rand::Rng;
+use subtle::ConstantTimeEq;
+
+use crate::descriptor::{DescriptorError, Multisig, SlapsSigner};
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+const VERSION: &str = "BSMS 1.0";
+
+#[derive(Debug)]
+pub enum BsmsError {
+    InvalidToken,
+    InvalidRecord(&'static str),
+    WrongToken,
+    Mac,
+    InvalidSignature(Fingerprint),
+    DuplicateSigner(Fingerprint),
+    TooManySigners,
+    MissingSigners { have: usize, need: usize },
+    Descriptor(DescriptorError),
+    Bdk(bdk::Error),
+}
+
+impl fmt::Display for BsmsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BsmsError::InvalidToken => write!(f, "Invalid BSMS token"),
+            BsmsError::InvalidRecord(reason) => write!(f, "Invalid BSMS record: {}", reason),
+            BsmsError::WrongToken => write!(f, "Record belongs to a different BSMS session"),
+            BsmsError::Mac => write!(f, "Record was tampered with or encrypted with another token"),
+            BsmsError::InvalidSignature(fingerprint) => {
+                write!(f, "Signature on the record from {} is invalid", fingerprint)
+            }
+            BsmsError::DuplicateSigner(fingerprint) => {
+                write!(f, "Already have a record from {}", fingerprint)
+            }
+            BsmsError::TooManySigners => write!(f, "All signer records have been imported"),
+            BsmsError::MissingSigners { have, need } => {
+                write!(f, "Have {} of {} signer records", have, need)
+            }
+            BsmsError::Descriptor(err) => write!(f, "{}", err),
+            BsmsError::Bdk(err) => write!(f, "Wallet error: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for BsmsError {}
+
+impl From<DescriptorError> for BsmsError {
+    fn from(err: DescriptorError) -> Self {
+        BsmsError::Descriptor(err)
+    }
+}
+
+impl From<bdk::Error> for BsmsError {
+    fn from(err: bdk::Error) -> Self {
+        BsmsError::Bdk(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncryptionLevel {
+    /// Token `00`, records are exchanged in plain text
+    None,
+    /// 8 byte token
+    Standard,
+    /// 16 byte token
+    Extended,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token(Vec<u8>);
+
+impl Token {
+    pub fn generate(level: EncryptionLevel) -> Self {
+        let mut rng = rand::thread_rng();
+        match level {
+            EncryptionLevel::None => Token(vec![0]),
+            EncryptionLevel::Standard => Token(rng.gen::<[u8; 8]>().to_vec()),
+            EncryptionLevel::Extended => Token(rng.gen::<[u8; 16]>().to_vec()),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.0 != [0]
+    }
+
+    /// PBKDF2-HMAC-SHA512 of the token with the salt "No SPOF", as specified by BIP129.
+    fn encryption_key(&self) -> [u8; 32] {
+        let mut engine = hmac::HmacEngine::<sha512::Hash>::new(&self.0);
+        engine.input(b"No SPOF");
+        engine.input(&1u32.to_be_bytes());
+        let mut u = hmac::Hmac::<sha512::Hash>::from_engine(engine).into_inner();
+        let mut t = u;
+        for _ in 1..2048 {
+            let mut engine = hmac::HmacEngine::<sha512::Hash>::new(&self.0);
+            engine.input(&u);
+            u = hmac::Hmac::<sha512::Hash>::from_engine(engine).into_inner();
+            for (t, u) in t.iter_mut().zip(u.iter()) {
+                *t ^= u;
+            }
+        }
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&t[..32]);
+        key
+    }
+
+    fn mac(&self, key: &[u8; 32], data: &[u8]) -> [u8; 32] {
+        let mac_key = sha256::Hash::hash(key);
+        let mut engine = hmac::HmacEngine::<sha256::Hash>::new(&mac_key[..]);
+        engine.input(self.to_string().as_bytes());
+        engine.input(data);
+        hmac::Hmac::<sha256::Hash>::from_engine(engine).into_inner()
+    }
+
+    /// Encrypts a record for exchange, or returns it unchanged without encryption.
+    pub fn encrypt(&self, data: &str) -> String {
+        if !self.is_encrypted() {
+            return data.to_string();
+        }
+        let key = self.encryption_key();
+        let mac = self.mac(&key, data.as_bytes());
+
+        let mut ciphertext = data.as_bytes().to_vec();
+        let mut cipher = Aes256Ctr::new((&key).into(), (&mac[..16]).into());
+        cipher.apply_keystream(&mut ciphertext);
+
+        let mut out = mac.to_vec();
+        out.extend(ciphertext);
+        out.to_hex()
+    }
+
+    pub fn decrypt(&self, contents: &str) -> Result<String, BsmsError> {
+        if !self.is_encrypted() {
+            return Ok(contents.to_string());
+        }
+        let bytes = Vec::<u8>::from_hex(contents.trim())
+            .map_err(|_| BsmsError::InvalidRecord("expected hex encoded ciphertext"))?;
+        if bytes.len() < 32 {
+            return Err(BsmsError::InvalidRecord("ciphertext is too short"));
+        }
+        let (mac, ciphertext) = bytes.split_at(32);
+        let key = self.encryption_key();
+
+        let mut plaintext = ciphertext.to_vec();
+        let mut cipher = Aes256Ctr::new((&key).into(), (&mac[..16]).into());
+        cipher.apply_keystream(&mut plaintext);
+
+        if !bool::from(self.mac(&key, &plaintext)[..].ct_eq(mac)) {
+            return Err(BsmsError::Mac);
+        }
+        String::from_utf8(plaintext).map_err(|_| BsmsError::InvalidRecord("not UTF-8"))
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.to_hex())
+    }
+}
+
+impl FromStr for Token {
+    type Err = BsmsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = Vec::<u8>::from_hex(s.trim()).map_err(|_| BsmsError::InvalidToken)?;
+        match bytes.len() {
+            1 if bytes[0] == 0 => Ok(Token(bytes)),
+            8 | 16 => Ok(Token(bytes)),
+            _ => Err(BsmsError::InvalidToken),
+        }
+    }
+}
+
+/// Round 1: the key a signer contributes, signed with that key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignerRecord {
+    pub token: Token,
+    pub key: SlapsSigner,
+    pub description: String,
+    pub signature: Vec<u8>,
+}
+
+impl SignerRecord {
+    pub fn parse(record: &str) -> Result<Self, BsmsError> {
+        let lines: Vec<&str> = record.lines().map(|line| line.trim()).collect();
+        if lines.len() < 5 {
+            return Err(BsmsError::InvalidRecord("expected 5 lines"));
+        }
+        if lines[0] != VERSION {
+            return Err(BsmsError::InvalidRecord("unsupported BSMS version"));
+        }
+        let key = lines[2]
+            .parse()
+            .map_err(|_| BsmsError::InvalidRecord("key must be an xpub with its origin"))?;
+        let signature = base64::decode(lines[4])
+            .map_err(|_| BsmsError::InvalidRecord("signature isn't base64"))?;
+
+        Ok(Self {
+            token: lines[1].parse()?,
+            key,
+            description: lines[3].to_string(),
+            signature,
+        })
+    }
+
+    fn message(&self) -> String {
+        format!("{}\n{}\n{}\n{}", VERSION, self.token, self.key, self.description)
+    }
+
+    /// Checks the record was signed by the key it contributes, using the Bitcoin Signed
+    /// Message format.
+    pub fn verify(&self) -> Result<(), BsmsError> {
+        let invalid = || BsmsError::InvalidSignature(*self.key.fingerprint);
+        if self.signature.len() != 65 {
+            return Err(invalid());
+        }
+        // 27 plus the recovery id, plus 4 for a compressed key and 8 or 12 for the segwit
+        // variants. Account keys are always compressed, so anything else isn't from this key.
+        if !(31..=42).contains(&self.signature[0]) {
+            return Err(invalid());
+        }
+
+        let message = self.message();
+        let mut data = b"\x18Bitcoin Signed Message:\n".to_vec();
+        data.extend(serialize(&VarInt(message.len() as u64)));
+        data.extend(message.as_bytes());
+        let hash = sha256d::Hash::hash(&data);
+
+        let secp = Secp256k1::verification_only();
+        let mut signature = Signature::from_compact(&self.signature[1..]).map_err(|_| invalid())?;
+        signature.normalize_s();
+        let message = Message::from_slice(&hash[..]).map_err(|_| invalid())?;
+        secp.verify(&message, &signature, &self.key.xpub.public_key.key)
+            .map_err(|_| invalid())
+    }
+}
+
+/// Collects signer records for a `threshold`-of-`total` wallet.
+#[derive(Debug, Clone)]
+pub struct Coordinator {
+    pub token: Token,
+    pub threshold: usize,
+    pub total: usize,
+    pub records: Vec<SignerRecord>,
+}
+
+impl Coordinator {
+    pub fn new(threshold: usize, total: usize, level: EncryptionLevel) -> Self {
+        Self {
+            token: Token::generate(level),
+            threshold,
+            total,
+            records: vec![],
+        }
+    }
+
+    /// Decrypts, parses and verifies a signer record file.
+    pub fn import_record(&mut self, contents: &str) -> Result<&SignerRecord, BsmsError> {
+        if self.records.len() >= self.total {
+            return Err(BsmsError::TooManySigners);
+        }
+        let record = SignerRecord::parse(&self.token.decrypt(contents)?)?;
+        if record.token != self.token {
+            return Err(BsmsError::WrongToken);
+        }
+        record.verify()?;
+        if self.records.iter().any(|r| r.key.fingerprint == record.key.fingerprint) {
+            return Err(BsmsError::DuplicateSigner(*record.key.fingerprint));
+        }
+
+        self.records.push(record);
+        Ok(self.records.last().expect("Just pushed a record"))
+    }
+
+    pub fn multisig(&self) -> Result<Multisig, BsmsError> {
+        if self.records.len() != self.total {
+            return Err(BsmsError::MissingSigners {
+                have: self.records.len(),
+                need: self.total,
+            });
+        }
+        let keys = self.records.iter().map(|r| r.key.clone()).collect();
+        Ok(Multisig::new(self.threshold, keys)?)
+    }
+
+    /// Round 2: the descriptor template, path restrictions and first address every cosigner
+    /// checks before accepting the wallet, encrypted with the session token.
+    pub fn descriptor_record(&self, network: Network) -> Result<String, BsmsError> {
+        let multisig = self.multisig()?;
+
+        let keys: Vec<String> = multisig.keys.iter().map(|key| format!("{}/**", key)).collect();
+        let template = format!("wsh(sortedmulti({},{}))", multisig.threshold, keys.join(","));
+        let checksum = get_checksum(&template).map_err(|_| DescriptorError::Checksum)?;
+
+        let wallet = Wallet::new_offline(
+            &multisig.descriptor(false, None)?,
+            None,
+            network,
+            MemoryDatabase::default(),
+        )?;
+        let first_address = wallet.get_new_address()?;
+
+        let record = format!(
+            "{}\n{}#{}\n/0/*,/1/*\n{}",
+            VERSION, template, checksum, first_address
+        );
+        Ok(self.token.encrypt(&record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values computed independently with Python's hashlib, hmac and cryptography
+    const TOKEN: &str = "a54044308ceac9b7";
+    const RECORD: &str = "BSMS 1.0\nwsh(sortedmulti(2,[2de67592/48'/1'/0'/2']tpub/**))\n/0/*,/1/*\nbcrt1qexample";
+    const ENCRYPTED: &str = "8effbbeace05d9b29b138cca1071b102d2a761a89062d20c0a3aaffdd198a5d467d5a4c510c5451784a2ba86bf44a43d320c90c3b52a2ac8b9f51b672aef631bea812e0961edd3eee71723da117ff617dcb6202024c9a68b421561c9928e952a9f45fb4ff8dbcd28c03eea1c36ae6adaa7e34a";
+
+    // Signed with the account key using rust-bitcoin's signed message support
+    const KEY: &str = "[2de67592/48'/1'/0'/2']tpubDFFgV6kiSbSwMDKhketWTo3hRuvdxCuK7Xzc4MW1joKTV9wGrp5Xbsvk79QhU3qNzkijKSvo18JzzdEfBx9QHWH3cqLYiQAKParawyXQH3k";
+    const SIGNATURE: &str = "HyVUmh8GgHpzRRF1Lsl9pW1aEbvXhxDD1KL/SMcW+7ZoJRtFLEFiJyqwzLw9LuFUO9IPqYKEILEyC9QQzDzVp6U=";
+    const OTHER_SIGNATURE: &str = "IJeQyXOAvpzedsgbC9tOD1h3ojL3cAcFfPh4VLpQhn86TdjBLdPjSvRcA5Ao85RSaSSZoaYGX4Pf+sbbrzzBHxQ=";
+
+    fn signer_record(signature: &str) -> String {
+        format!("BSMS 1.0\n00\n{}\nSigner 1\n{}", KEY, signature)
+    }
+
+    #[test]
+    fn derives_encryption_key() {
+        let token: Token = TOKEN.parse().unwrap();
+        assert_eq!(
+            token.encryption_key().to_hex(),
+            "847fba0c67fac00c7db56b106ac5040de236765fbb7e3bf7dedcbb6b3baeaa61"
+        );
+        let token: Token = "00112233445566778899aabbccddeeff".parse().unwrap();
+        assert_eq!(
+            token.encryption_key().to_hex(),
+            "82573aaa64856d8d575b5c20fd6d9367a6203aa49998dc5da69c7da46a9c22b9"
+        );
+    }
+
+    #[test]
+    fn encrypts_and_decrypts() {
+        let token: Token = TOKEN.parse().unwrap();
+        assert_eq!(token.encrypt(RECORD), ENCRYPTED);
+        assert_eq!(token.decrypt(ENCRYPTED).unwrap(), RECORD);
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertext() {
+        let token: Token = TOKEN.parse().unwrap();
+        let mut tampered = Vec::<u8>::from_hex(ENCRYPTED).unwrap();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(matches!(token.decrypt(&tampered.to_hex()), Err(BsmsError::Mac)));
+
+        let other: Token = "00112233445566778899aabbccddeeff".parse().unwrap();
+        assert!(matches!(other.decrypt(ENCRYPTED), Err(BsmsError::Mac)));
+    }
+
+    #[test]
+    fn unencrypted_token_passes_through() {
+        let token: Token = "00".parse().unwrap();
+        assert!(!token.is_encrypted());
+        assert_eq!(token.encrypt(RECORD), RECORD);
+        assert!("0011".parse::<Token>().is_err());
+    }
+
+    #[test]
+    fn verifies_signer_record() {
+        let record = SignerRecord::parse(&signer_record(SIGNATURE)).unwrap();
+        assert_eq!(record.description, "Signer 1");
+        record.verify().unwrap();
+    }
+
+    #[test]
+    fn rejects_signature_from_another_key() {
+        let record = SignerRecord::parse(&signer_record(OTHER_SIGNATURE)).unwrap();
+        assert!(matches!(record.verify(), Err(BsmsError::InvalidSignature(_))));
+    }
+
+    #[test]
+    fn rejects_uncompressed_header() {
+        let mut record = SignerRecord::parse(&signer_record(SIGNATURE)).unwrap();
+        record.signature[0] = 27;
+        assert!(matches!(record.verify(), Err(BsmsError::InvalidSignature(_))));
+        record.signature[0] = 43;
+        assert!(matches!(record.verify(), Err(BsmsError::InvalidSignature(_))));
+    }
+
+    #[test]
+    fn rejects_edited_record() {
+        let edited = signer_record(SIGNATURE).replace("Signer 1", "Signer 2");
+        let record = SignerRecord::parse(&edited).unwrap();
+        assert!(matches!(record.verify(), Err(BsmsError::InvalidSignature(_))));
+    }
+}
@@ -0,0 +1,570 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use bdk::{
+    bitcoin::util::{
+        base58,
+        bip32::{ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint},
+    },
+    Network,
+    descriptor::{get_checksum, Descriptor},
+    miniscript::DescriptorPublicKey,
+};
+
+#[derive(Debug)]
+pub enum DescriptorError {
+    InvalidKey(String),
+    InvalidThreshold { threshold: usize, keys: usize },
+    DuplicateKey(Fingerprint),
+    Checksum,
+    InvalidChecksum { expected: String, found: String },
+    WrongNetwork,
+    Miniscript(bdk::miniscript::Error),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::InvalidKey(key) => write!(f, "Invalid key: {}", key),
+            DescriptorError::InvalidThreshold { threshold, keys } => {
+                write!(f, "Can't make a {}-of-{} multisig", threshold, keys)
+            }
+            DescriptorError::DuplicateKey(fingerprint) => {
+                write!(f, "Key {} was added more than once", fingerprint)
+            }
+            DescriptorError::Checksum => write!(f, "Failed to compute the descriptor checksum"),
+            DescriptorError::InvalidChecksum { expected, found } => {
+                write!(f, "Descriptor checksum is {} but should be {}", found, expected)
+            }
+            DescriptorError::WrongNetwork => write!(f, "Key is for a different network than the wallet"),
+            DescriptorError::Miniscript(err) => write!(f, "Invalid descriptor: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DescriptorError {}
+
+impl From<bdk::miniscript::Error> for DescriptorError {
+    fn from(err: bdk::miniscript::Error) -> Self {
+        DescriptorError::Miniscript(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptType {
+    Pkh,
+    ShWpkh,
+    Wpkh,
+}
+
+impl ScriptType {
+    /// The script type of a single key descriptor, `None` for multisig or anything else.
+    pub fn from_descriptor(descriptor: &str) -> Option<Self> {
+        if descriptor.starts_with("pkh(") {
+            Some(ScriptType::Pkh)
+        } else if descriptor.starts_with("sh(wpkh(") {
+            Some(ScriptType::ShWpkh)
+        } else if descriptor.starts_with("wpkh(") {
+            Some(ScriptType::Wpkh)
+        } else {
+            None
+        }
+    }
+
+    /// Wraps a key expression in this script type's descriptor.
+    fn wrap(&self, key: &str) -> String {
+        match self {
+            ScriptType::Pkh => format!("pkh({})", key),
+            ScriptType::ShWpkh => format!("sh(wpkh({}))", key),
+            ScriptType::Wpkh => format!("wpkh({})", key),
+        }
+    }
+
+    fn purpose(&self) -> u32 {
+        match self {
+            ScriptType::Pkh => 44,
+            ScriptType::ShWpkh => 49,
+            ScriptType::Wpkh => 84,
+        }
+    }
+
+    /// The standard `m/purpose'/coin'/account'` path for this script type.
+    pub fn account_path(&self, network: Network, account: u32) -> DerivationPath {
+        hardened_path(&[self.purpose(), coin_type(network), account])
+    }
+}
+
+fn coin_type(network: Network) -> u32 {
+    match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    }
+}
+
+fn hardened_path(indexes: &[u32]) -> DerivationPath {
+    let path: Vec<ChildNumber> = indexes
+        .iter()
+        .map(|index| ChildNumber::from_hardened_idx(*index).expect("Index is below 2^31"))
+        .collect();
+    DerivationPath::from(path)
+}
+
+/// The BIP48 `m/48'/coin'/account'/2'` path for p2wsh multisig keys.
+pub fn multisig_account_path(network: Network, account: u32) -> DerivationPath {
+    hardened_path(&[48, coin_type(network), account, 2])
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Single(ScriptType),
+    SortedMulti(usize),
+}
+
+/// Builds descriptors from keys with their origins, for example
+/// `wpkh([d34db33f/84'/1'/0']tpub.../0/*)#checksum`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorBuilder {
+    kind: Kind,
+    keys: Vec<SlapsSigner>,
+    branch: u32,
+    index: Option<u32>,
+    checksum: bool,
+}
+
+impl DescriptorBuilder {
+    pub fn new(script_type: ScriptType, key: SlapsSigner) -> Self {
+        Self {
+            kind: Kind::Single(script_type),
+            keys: vec![key],
+            branch: 0,
+            index: None,
+            checksum: true,
+        }
+    }
+
+    /// A `wsh(sortedmulti(threshold, keys...))` descriptor.
+    pub fn sorted_multi(threshold: usize, keys: Vec<SlapsSigner>) -> Self {
+        Self {
+            kind: Kind::SortedMulti(threshold),
+            keys,
+            branch: 0,
+            index: None,
+            checksum: true,
+        }
+    }
+
+    /// Use the change (internal) keychain branch instead of the receive branch.
+    pub fn change(mut self, change: bool) -> Self {
+        self.branch = change as u32;
+        self
+    }
+
+    /// Derive a single address index instead of a `*` wildcard.
+    pub fn index(mut self, index: u32) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    pub fn build(&self) -> Result<String, DescriptorError> {
+        if let Kind::SortedMulti(threshold) = self.kind {
+            if threshold == 0 || threshold > self.keys.len() {
+                return Err(DescriptorError::InvalidThreshold {
+                    threshold,
+                    keys: self.keys.len(),
+                });
+            }
+        }
+        let mainnet = |key: &SlapsSigner| key.xpub.network == Network::Bitcoin;
+        if self.keys.iter().any(|key| mainnet(key) != mainnet(&self.keys[0])) {
+            return Err(DescriptorError::WrongNetwork);
+        }
+
+        let index = match self.index {
+            Some(index) => index.to_string(),
+            None => String::from("*"),
+        };
+        let keys: Vec<String> = self
+            .keys
+            .iter()
+            .map(|key| format!("{}/{}/{}", key, self.branch, index))
+            .collect();
+
+        let descriptor = match &self.kind {
+            Kind::Single(script_type) => script_type.wrap(&keys[0]),
+            Kind::SortedMulti(threshold) => {
+                format!("wsh(sortedmulti({},{}))", threshold, keys.join(","))
+            }
+        };
+
+        if self.checksum {
+            with_checksum(&descriptor)
+        } else {
+            Descriptor::<DescriptorPublicKey>::from_str(&descriptor)?;
+            Ok(descriptor)
+        }
+    }
+}
+
+/// Parses an extended public key, converting SLIP-132 versions (ypub, zpub, upub, vpub...) to
+/// plain xpub/tpub and returning the script type they imply.
+pub fn parse_xpub(s: &str) -> Result<(ExtendedPubKey, Option<ScriptType>), DescriptorError> {
+    let invalid = || DescriptorError::InvalidKey(s.to_string());
+    let mut data = base58::from_check(s.trim()).map_err(|_| invalid())?;
+    if data.len() != 78 {
+        return Err(invalid());
+    }
+
+    const XPUB: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+    const TPUB: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+    let (version, script_type) = match &data[..4] {
+        [0x04, 0x88, 0xb2, 0x1e] => (XPUB, None),
+        [0x04, 0x9d, 0x7c, 0xb2] => (XPUB, Some(ScriptType::ShWpkh)),
+        [0x04, 0xb2, 0x47, 0x46] => (XPUB, Some(ScriptType::Wpkh)),
+        [0x04, 0x35, 0x87, 0xcf] => (TPUB, None),
+        [0x04, 0x4a, 0x52, 0x62] => (TPUB, Some(ScriptType::ShWpkh)),
+        [0x04, 0x5f, 0x1c, 0xf6] => (TPUB, Some(ScriptType::Wpkh)),
+        _ => return Err(invalid()),
+    };
+    data[..4].copy_from_slice(&version);
+
+    let xpub = ExtendedPubKey::from_str(&base58::check_encode_slice(&data)).map_err(|_| invalid())?;
+    Ok((xpub, script_type))
+}
+
+/// Checks the `#checksum` of a descriptor if it has one, returning the descriptor with its
+/// checksum once miniscript accepts it.
+pub fn validate(descriptor: &str) -> Result<String, DescriptorError> {
+    with_checksum(check_checksum(descriptor)?)
+}
+
+/// Checks the `#checksum` of a descriptor if it has one and returns the part before it,
+/// without parsing it. For descriptors miniscript can't read yet, like `<0;1>` multipath ones.
+pub fn check_checksum(descriptor: &str) -> Result<&str, DescriptorError> {
+    let mut parts = descriptor.trim().splitn(2, '#');
+    let body = parts.next().unwrap_or("");
+    if let Some(found) = parts.next() {
+        let expected = get_checksum(body).map_err(|_| DescriptorError::Checksum)?;
+        if found != expected {
+            return Err(DescriptorError::InvalidChecksum {
+                expected,
+                found: found.to_string(),
+            });
+        }
+    }
+    Ok(body)
+}
+
+/// The master fingerprints in the key origins of a descriptor.
+pub fn fingerprints(descriptor: &str) -> Vec<Fingerprint> {
+    let mut fingerprints: Vec<Fingerprint> = descriptor
+        .split('[')
+        .skip(1)
+        .filter_map(|origin| origin.get(..8))
+        .filter_map(|fingerprint| Fingerprint::from_str(fingerprint).ok())
+        .collect();
+    fingerprints.sort();
+    fingerprints.dedup();
+    fingerprints
+}
+
+/// Checks every extended key in a descriptor is for `network`. Testnet, signet and regtest share
+/// tpubs, so only mainnet against the rest can be told apart.
+pub fn check_network(descriptor: &str, network: Network) -> Result<(), DescriptorError> {
+    let keys = descriptor
+        .split(|c| matches!(c, '[' | ']' | '(' | ')' | ',' | '/' | '#'))
+        .filter_map(|token| parse_xpub(token).ok());
+    for (xpub, _) in keys {
+        if (xpub.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+            return Err(DescriptorError::WrongNetwork);
+        }
+    }
+    Ok(())
+}
+
+/// The account key of a single key descriptor like `wpkh([d34db33f/84'/1'/0']tpub.../0/*)`.
+pub fn single_key(descriptor: &str) -> Option<SlapsSigner> {
+    ScriptType::from_descriptor(descriptor)?;
+    let origin_start = descriptor.find('[')?;
+    let origin_end = descriptor.find(']')?;
+    let key_end = origin_end + descriptor[origin_end..].find(|c| c == '/' || c == ')')?;
+    descriptor[origin_start..key_end].parse().ok()
+}
+
+/// The threshold and keys of a `wsh(sortedmulti(...))` descriptor on the receive or change branch.
+pub fn sorted_multi(descriptor: &str) -> Option<Multisig> {
+    let body = descriptor.splitn(2, '#').next()?;
+    let inner = body.strip_prefix("wsh(sortedmulti(")?.strip_suffix("))")?;
+    let mut parts = inner.split(',');
+    let threshold = parts.next()?.parse().ok()?;
+    let keys = parts
+        .map(|key| {
+            let origin_end = key.find(']').unwrap_or(0);
+            let key_end = key[origin_end..].find('/').map_or(key.len(), |i| origin_end + i);
+            key[..key_end].parse()
+        })
+        .collect::<Result<Vec<SlapsSigner>, _>>()
+        .ok()?;
+    Multisig::new(threshold, keys).ok()
+}
+
+/// An account xpub with the origin it was derived from, written `[d34db33f/48'/1'/0'/2']tpub...`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlapsSigner {
+    pub xpub: Arc<ExtendedPubKey>,
+    pub fingerprint: Arc<Fingerprint>,
+    pub derivation_path: Arc<DerivationPath>,
+}
+
+impl SlapsSigner {
+    pub fn new(
+        fingerprint: Fingerprint,
+        derivation_path: DerivationPath,
+        xpub: ExtendedPubKey,
+    ) -> Self {
+        Self {
+            fingerprint: Arc::new(fingerprint),
+            derivation_path: Arc::new(derivation_path),
+            xpub: Arc::new(xpub),
+        }
+    }
+}
+
+impl fmt::Display for SlapsSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let origin = self
+            .derivation_path
+            .to_string()
+            .replacen("m", &self.fingerprint.to_string(), 1);
+        write!(f, "[{}]{}", origin, self.xpub)
+    }
+}
+
+impl FromStr for SlapsSigner {
+    type Err = DescriptorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DescriptorError::InvalidKey(s.to_string());
+        let s = s.trim();
+
+        let origin_end = s.find(']').ok_or_else(invalid)?;
+        if !s.starts_with('[') {
+            return Err(invalid());
+        }
+        let origin = &s[1..origin_end];
+        let mut origin_parts = origin.splitn(2, '/');
+        let fingerprint =
+            Fingerprint::from_str(origin_parts.next().ok_or_else(invalid)?).map_err(|_| invalid())?;
+        let derivation_path = match origin_parts.next() {
+            Some(path) => DerivationPath::from_str(&format!("m/{}", path)).map_err(|_| invalid())?,
+            None => DerivationPath::from(vec![]),
+        };
+        let (xpub, _) = parse_xpub(&s[origin_end + 1..])?;
+
+        Ok(Self::new(fingerprint, derivation_path, xpub))
+    }
+}
+
+/// Adds the checksum to `descriptor` and makes sure miniscript can parse the result.
+pub fn with_checksum(descriptor: &str) -> Result<String, DescriptorError> {
+    let checksum = get_checksum(descriptor).map_err(|_| DescriptorError::Checksum)?;
+    let descriptor = format!("{}#{}", descriptor, checksum);
+    Descriptor::<DescriptorPublicKey>::from_str(&descriptor)?;
+    Ok(descriptor)
+}
+
+/// The keys and threshold of a `wsh(sortedmulti(...))` wallet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Multisig {
+    pub threshold: usize,
+    pub keys: Vec<SlapsSigner>,
+}
+
+impl Multisig {
+    pub fn new(threshold: usize, keys: Vec<SlapsSigner>) -> Result<Self, DescriptorError> {
+        if threshold == 0 || threshold > keys.len() {
+            return Err(DescriptorError::InvalidThreshold {
+                threshold,
+                keys: keys.len(),
+            });
+        }
+        for (index, key) in keys.iter().enumerate() {
+            if keys[..index].iter().any(|other| other.fingerprint == key.fingerprint) {
+                return Err(DescriptorError::DuplicateKey(*key.fingerprint));
+            }
+        }
+        Ok(Self { threshold, keys })
+    }
+
+    /// The descriptor for the receive or change branch, or for a single `index` on that branch.
+    pub fn descriptor(&self, change: bool, index: Option<u32>) -> Result<String, DescriptorError> {
+        let mut builder = DescriptorBuilder::sorted_multi(self.threshold, self.keys.clone()).change(change);
+        if let Some(index) = index {
+            builder = builder.index(index);
+        }
+        builder.build()
+    }
+
+    /// The setup file Coldcard imports from its SD card to register this wallet.
+    pub fn coldcard_file(&self, name: &str) -> String {
+        // Coldcard only accepts names up to 20 characters
+        let name: String = name.chars().take(20).collect();
+        let mut file = String::from("# Coldcard Multisig setup file (created by Slaps Roof Of Wallet)\n#\n");
+        file += &format!("Name: {}\n", name);
+        file += &format!("Policy: {} of {}\n", self.threshold, self.keys.len());
+        file += "Format: P2WSH\n";
+        for key in &self.keys {
+            file += &format!("\nDerivation: {}\n", key.derivation_path);
+            file += &format!("{}: {}\n", key.fingerprint.to_string().to_uppercase(), key.xpub);
+        }
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TPUB: &str = "[2de67592/48'/1'/0'/2']tpubDFFgV6kiSbSwMDKhketWTo3hRuvdxCuK7Xzc4MW1joKTV9wGrp5Xbsvk79QhU3qNzkijKSvo18JzzdEfBx9QHWH3cqLYiQAKParawyXQH3k";
+    // The BIP32 test vector 1 master key
+    const XPUB: &str = "[3442193e/48'/0'/0'/2']xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+    // The BIP32 test vector 1 and 2 master keys and the vector 1 m/0' key, with their
+    // fingerprints
+    const MASTER_1: (&str, &str, &str) = (
+        "3442193e",
+        "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+        "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp",
+    );
+    const MASTER_2: (&str, &str, &str) = (
+        "bd16bee5",
+        "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB",
+        "tpubD6NzVbkrYhZ4XJDrzRvuxHEyQaPd1mwwdDofEJwekX18tAdsqeKfxss79AJzg1431FybXg5rfpTrJF4iAhyR7RubberdzEQXiRmXGADH2eA",
+    );
+    const CHILD_1: (&str, &str, &str) = (
+        "5c1bd648",
+        "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+        "tpubD8eQVK4Kdxg3gHrF62jGP7dKVCoYiEB8dFSpuTawkL5YxTus5j5pf83vaKnii4bc6v2NVEy81P2gYrJczYne3QNNwMTS53p5uzDyHvnw2jm",
+    );
+
+    /// The mainnet or testnet version of `key` with its fingerprint and `path` as origin.
+    fn signer(key: (&str, &str, &str), network: Network, path: DerivationPath) -> SlapsSigner {
+        let xpub = if network == Network::Bitcoin { key.1 } else { key.2 };
+        SlapsSigner::new(
+            Fingerprint::from_str(key.0).unwrap(),
+            path,
+            ExtendedPubKey::from_str(xpub).unwrap(),
+        )
+    }
+
+    #[test]
+    fn builds_single_key_descriptors() {
+        let cases = [
+            (Network::Bitcoin, ScriptType::Pkh, "pkh([3442193e/44'/0'/0']{}/0/*)#snpxwmh5"),
+            (Network::Bitcoin, ScriptType::ShWpkh, "sh(wpkh([3442193e/49'/0'/0']{}/0/*))#ap3ljzu3"),
+            (Network::Bitcoin, ScriptType::Wpkh, "wpkh([3442193e/84'/0'/0']{}/0/*)#65hsgkeq"),
+            (Network::Testnet, ScriptType::Pkh, "pkh([3442193e/44'/1'/0']{}/0/*)#8le8g2rt"),
+            (Network::Testnet, ScriptType::ShWpkh, "sh(wpkh([3442193e/49'/1'/0']{}/0/*))#qve05cdz"),
+            (Network::Testnet, ScriptType::Wpkh, "wpkh([3442193e/84'/1'/0']{}/0/*)#f6npgexw"),
+        ];
+        for (network, script_type, expected) in cases.iter() {
+            let key = signer(MASTER_1, *network, script_type.account_path(*network, 0));
+            let xpub = key.xpub.to_string();
+            let descriptor = DescriptorBuilder::new(*script_type, key).build().unwrap();
+            assert_eq!(descriptor, expected.replace("{}", &xpub));
+        }
+    }
+
+    #[test]
+    fn builds_single_addresses_on_the_change_branch() {
+        let path = ScriptType::Wpkh.account_path(Network::Bitcoin, 0);
+        let builder = DescriptorBuilder::new(ScriptType::Wpkh, signer(MASTER_1, Network::Bitcoin, path))
+            .change(true)
+            .index(5);
+        let expected = format!("wpkh([3442193e/84'/0'/0']{}/1/5)", MASTER_1.1);
+        assert_eq!(builder.build().unwrap(), format!("{}#gxkhkckv", expected));
+        assert_eq!(builder.checksum(false).build().unwrap(), expected);
+
+        let path = ScriptType::Wpkh.account_path(Network::Testnet, 0);
+        let builder = DescriptorBuilder::new(ScriptType::Wpkh, signer(MASTER_1, Network::Testnet, path))
+            .change(true)
+            .index(5);
+        let expected = format!("wpkh([3442193e/84'/1'/0']{}/1/5)#mgjxkhfz", MASTER_1.2);
+        assert_eq!(builder.build().unwrap(), expected);
+    }
+
+    #[test]
+    fn builds_sorted_multisig_descriptors() {
+        let cases = [
+            (Network::Bitcoin, false, "ner7r5mr"),
+            (Network::Bitcoin, true, "kvgyajtt"),
+            (Network::Testnet, false, "78chmq0p"),
+            (Network::Testnet, true, "mjnd9xlf"),
+        ];
+        for (network, change, checksum) in cases.iter() {
+            let keys: Vec<SlapsSigner> = [MASTER_1, MASTER_2, CHILD_1]
+                .iter()
+                .map(|key| signer(*key, *network, multisig_account_path(*network, 0)))
+                .collect();
+            let coin = if *network == Network::Bitcoin { 0 } else { 1 };
+            let branch = *change as u32;
+            let expected_keys: Vec<String> = keys
+                .iter()
+                .map(|key| format!("[{}/48'/{}'/0'/2']{}/{}/*", key.fingerprint, coin, key.xpub, branch))
+                .collect();
+            let expected = format!("wsh(sortedmulti(2,{}))#{}", expected_keys.join(","), checksum);
+            let descriptor = DescriptorBuilder::sorted_multi(2, keys).change(*change).build().unwrap();
+            assert_eq!(descriptor, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_thresholds() {
+        let keys: Vec<SlapsSigner> = [MASTER_1, MASTER_2]
+            .iter()
+            .map(|key| signer(*key, Network::Bitcoin, multisig_account_path(Network::Bitcoin, 0)))
+            .collect();
+        for threshold in [0, 3].iter() {
+            let result = DescriptorBuilder::sorted_multi(*threshold, keys.clone()).build();
+            assert!(matches!(
+                result,
+                Err(DescriptorError::InvalidThreshold { threshold: t, keys: 2 }) if t == *threshold
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_mixed_networks() {
+        let keys = vec![
+            signer(MASTER_1, Network::Bitcoin, multisig_account_path(Network::Bitcoin, 0)),
+            signer(MASTER_2, Network::Testnet, multisig_account_path(Network::Testnet, 0)),
+        ];
+        let result = DescriptorBuilder::sorted_multi(1, keys).build();
+        assert!(matches!(result, Err(DescriptorError::WrongNetwork)));
+    }
+
+    #[test]
+    fn fingerprints_are_deduplicated() {
+        let descriptor = format!("wsh(sortedmulti(1,{}/0/*,{}/0/*,{}/0/*))", TPUB, XPUB, TPUB);
+        let expected: Vec<Fingerprint> = vec![
+            Fingerprint::from_str("2de67592").unwrap(),
+            Fingerprint::from_str("3442193e").unwrap(),
+        ];
+        assert_eq!(fingerprints(&descriptor), expected);
+    }
+
+    #[test]
+    fn checks_key_networks() {
+        let testnet = format!("wpkh({}/0/*)", TPUB);
+        assert!(check_network(&testnet, Network::Testnet).is_ok());
+        assert!(check_network(&testnet, Network::Regtest).is_ok());
+        assert!(matches!(check_network(&testnet, Network::Bitcoin), Err(DescriptorError::WrongNetwork)));
+
+        let mixed = format!("wsh(sortedmulti(1,{}/0/*,{}/0/*))", TPUB, XPUB);
+        assert!(matches!(check_network(&mixed, Network::Testnet), Err(DescriptorError::WrongNetwork)));
+        assert!(matches!(check_network(&mixed, Network::Bitcoin), Err(DescriptorError::WrongNetwork)));
+    }
+}
@@ -1,8 +1,102 @@
-use crate::ArcStr;
-use std::{str::FromStr, sync::Arc};
+use crate::{descriptor::{multisig_account_path, Multisig, ScriptType, SlapsSigner}, ArcStr};
+use std::{fmt, str::FromStr, sync::Arc};
 
-use bdk::{bitcoin::util::bip32::{DerivationPath, Fingerprint}, descriptor::Descriptor};
+use bdk::{bitcoin::{util::bip32::{DerivationPath, Fingerprint}, Network}, descriptor::Descriptor, miniscript::DescriptorPublicKey};
+use hwi::error::{Error as HWIError, ErrorCode};
 use hwi::interface::HWIDevice;
+use hwi::types::HWIAddressType;
+use serde::{Deserialize, Serialize};
+
+/// Why a call to a hardware device through HWI failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceError {
+    NotFound,
+    UserRejected,
+    UnsupportedInput(String),
+    Timeout,
+    Locked,
+    WrongPin,
+    PassphraseRequired,
+    Busy,
+    HWIUnavailable(String),
+    Other(String),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::NotFound => write!(f, "Device not found. Is it plugged in?"),
+            DeviceError::UserRejected => write!(f, "Rejected on the device"),
+            DeviceError::UnsupportedInput(msg) => {
+                write!(f, "The device can't sign this transaction: {}", msg)
+            }
+            DeviceError::Timeout => write!(f, "Timed out waiting for the device"),
+            DeviceError::Locked => write!(f, "Device is locked. Unlock it with your PIN first"),
+            DeviceError::WrongPin => write!(f, "Wrong PIN"),
+            DeviceError::PassphraseRequired => write!(f, "Device needs a passphrase"),
+            DeviceError::Busy => write!(f, "Device is busy with another request"),
+            DeviceError::HWIUnavailable(msg) => write!(f, "Couldn't run HWI: {}", msg),
+            DeviceError::Other(msg) => write!(f, "Device error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+impl From<HWIError> for DeviceError {
+    fn from(err: HWIError) -> Self {
+        match err {
+            HWIError::HWIError(msg, code) => {
+                let lowercase = msg.to_lowercase();
+                // HWI doesn't have a code for timeouts or a bad PIN, they only show up in the message
+                if lowercase.contains("timeout") || lowercase.contains("timed out") {
+                    return DeviceError::Timeout;
+                }
+                if lowercase.contains("pin") && lowercase.contains("invalid") {
+                    return DeviceError::WrongPin;
+                }
+                match code {
+                    Some(ErrorCode::NoDeviceType)
+                    | Some(ErrorCode::UnknownDeviceType)
+                    | Some(ErrorCode::DeviceConnError) => DeviceError::NotFound,
+                    Some(ErrorCode::ActionCanceled) => DeviceError::UserRejected,
+                    Some(ErrorCode::InvalidTx)
+                    | Some(ErrorCode::NotImplemented)
+                    | Some(ErrorCode::UnavailableAction) => DeviceError::UnsupportedInput(msg),
+                    Some(ErrorCode::DeviceNotReady) => DeviceError::Locked,
+                    Some(ErrorCode::NoPassword) => DeviceError::PassphraseRequired,
+                    Some(ErrorCode::DeviceBusy) => DeviceError::Busy,
+                    _ => DeviceError::Other(msg),
+                }
+            }
+            HWIError::IOError(err) => DeviceError::HWIUnavailable(err.to_string()),
+            err => DeviceError::Other(format!("{:?}", err)),
+        }
+    }
+}
+
+/// Whether a device knows about a multisig wallet, so it can verify change and show addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RegistrationStatus {
+    Unregistered,
+    /// The device stored the wallet policy and will recognize it from now on.
+    Registered,
+    /// The device showed the wallet's first address from its descriptor but doesn't keep it.
+    AddressVerified,
+    /// The setup file was exported for an air-gapped device, we can't tell if it was imported.
+    FileExported,
+}
+
+impl fmt::Display for RegistrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistrationStatus::Unregistered => write!(f, "not registered"),
+            RegistrationStatus::Registered => write!(f, "registered"),
+            RegistrationStatus::AddressVerified => write!(f, "address verified, not registered"),
+            RegistrationStatus::FileExported => write!(f, "setup file exported"),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct SlapsDevice {
@@ -50,13 +144,68 @@ impl SlapsDevice {
         self.hwi_device.path.clone().into()
     }
 
-    pub fn get_xpub(&self) -> ArcStr {
+    pub fn get_xpub(&self, account: u32) -> Result<ArcStr, DeviceError> {
+        let derivation_path = ScriptType::Wpkh.account_path(Network::Regtest, account);
+        Ok(self.get_signer(derivation_path)?.xpub.to_string().into())
+    }
+
+    /// The xpub at `derivation_path` along with its origin.
+    pub fn get_signer(&self, derivation_path: DerivationPath) -> Result<SlapsSigner, DeviceError> {
         // This bool is for testnet but I think it also works for regtest
         let using_regtest = true;
-        // TODO: shouldn't there be a new() fn on DerivationPath for building this?
-        let derivation_path = DerivationPath::from_str("m/84h/1h/0h").expect("Failed to get DerivationPath from string");
-        self.hwi_device.get_xpub(&derivation_path, using_regtest).expect("Failed to get xpub").xpub.to_string().into()
+        let xpub = self.hwi_device.get_xpub(&derivation_path, using_regtest)?.xpub;
+        Ok(SlapsSigner::new(self.get_fingerprint(), derivation_path, xpub))
+    }
+
+    /// The BIP48 p2wsh account key used as this device's share of a multisig wallet.
+    pub fn get_multisig_signer(&self, account: u32) -> Result<SlapsSigner, DeviceError> {
+        self.get_signer(multisig_account_path(Network::Regtest, account))
+    }
+
+    /// Shows the address at `path` on the device screen and returns the address the device derived.
+    pub fn display_address(
+        &self,
+        path: &DerivationPath,
+        address_type: HWIAddressType,
+    ) -> Result<ArcStr, DeviceError> {
+        let using_regtest = true;
+        let address = self
+            .hwi_device
+            .display_address_with_path(path, address_type, using_regtest)?;
+        Ok(address.address.to_string().into())
+    }
 
+    /// Coldcard can't register a multisig wallet over HWI and has to import a setup file instead.
+    pub fn needs_registration_file(&self) -> bool {
+        self.hwi_device.model.to_lowercase().contains("coldcard")
+    }
+
+    /// Whether showing a multisig address makes this device store the wallet policy. Jade and
+    /// BitBox02 prompt to register it, the rest (Ledger needs its own policy registration and
+    /// HMAC, which our HWI can't do) only display the address.
+    pub fn keeps_multisig_policy(&self) -> bool {
+        let model = self.hwi_device.model.to_lowercase();
+        model.contains("jade") || model.contains("bitbox02")
+    }
+
+    /// Registers `multisig` by showing its first receive address, which devices that keep a
+    /// list of wallets use to prompt for registration.
+    pub fn register_multisig(&self, multisig: &Multisig) -> Result<ArcStr, DeviceError> {
+        if self.needs_registration_file() {
+            return Err(DeviceError::UnsupportedInput(
+                "this device registers multisig wallets from a setup file".into(),
+            ));
+        }
+        let using_regtest = true;
+        let descriptor = multisig
+            .descriptor(false, Some(0))
+            .map_err(|err| DeviceError::Other(err.to_string()))?;
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&descriptor)
+            .map_err(|err| DeviceError::Other(err.to_string()))?;
+        let address = self
+            .hwi_device
+            .display_address_with_desc(&descriptor, using_regtest)?;
+        Ok(address.address.to_string().into())
     }
 
     pub fn get_device(&self) -> HWIDevice {
@@ -96,19 +245,9 @@ impl SlapsDevices {
         self.devices.iter().map(|d| d.clone()).collect()
     }
 
-    // TODO: This should return an Option?
-    pub fn get_device_by_fingerprint(&self, fingerprint: Fingerprint) -> &SlapsDevice {
-        let device = self
-            .devices
+    pub fn get_device_by_fingerprint(&self, fingerprint: Fingerprint) -> Option<&SlapsDevice> {
+        self.devices
             .iter()
-            .find_map(|d| {
-                if d.get_fingerprint() == fingerprint {
-                    Some(d)
-                } else {
-                    None
-                }
-            })
-            .expect("Couldn't find a device with that fingerprint");
-        device
+            .find(|d| d.get_fingerprint() == fingerprint)
     }
 }
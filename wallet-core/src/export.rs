@@ -0,0 +1,353 @@
+//! Wallet files for other coordinators, so cosigners can watch and spend from their own
+//! software.
+
+use std::fmt;
+
+use bdk::bitcoin::{util::base58, util::bip32::ExtendedPubKey, Network};
+use serde_json::{json, Value};
+
+use crate::{
+    descriptor::{self, ScriptType, SlapsSigner},
+    SlapsWallet,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// `importdescriptors` RPC arguments
+    BitcoinCore,
+    /// Specter's wallet JSON, which Sparrow imports too
+    Specter,
+    Electrum,
+    /// Multisig setup file for Coldcard's SD card import
+    Coldcard,
+}
+
+impl ExportFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Coldcard => "txt",
+            _ => "json",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::BitcoinCore => write!(f, "Bitcoin Core"),
+            ExportFormat::Specter => write!(f, "Sparrow/Specter"),
+            ExportFormat::Electrum => write!(f, "Electrum"),
+            ExportFormat::Coldcard => write!(f, "Coldcard"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportError {
+    /// The wallet's descriptors have no equivalent in this format
+    Unsupported(ExportFormat),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Unsupported(ExportFormat::Coldcard) => {
+                write!(f, "Coldcard setup files are only for multisig wallets")
+            }
+            ExportError::Unsupported(format) => {
+                write!(f, "This wallet type can't be exported to {}", format)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+pub fn export(wallet: &SlapsWallet, format: ExportFormat) -> Result<String, ExportError> {
+    match format {
+        ExportFormat::BitcoinCore => Ok(bitcoin_core(wallet)),
+        ExportFormat::Specter => Ok(specter(wallet)),
+        ExportFormat::Electrum => electrum(wallet),
+        ExportFormat::Coldcard => wallet
+            .export_registration_file(&wallet.name)
+            .ok_or(ExportError::Unsupported(format)),
+    }
+}
+
+fn pretty(value: Value) -> String {
+    serde_json::to_string_pretty(&value).expect("JSON values always serialize")
+}
+
+fn bitcoin_core(wallet: &SlapsWallet) -> String {
+    // A timestamp of 0 rescans the whole chain, we don't know when the wallet was first used
+    let request = |descriptor: &str, internal: bool| {
+        json!({
+            "desc": descriptor,
+            "active": true,
+            "internal": internal,
+            "timestamp": 0,
+            "range": [0, 999],
+        })
+    };
+    pretty(json!([
+        request(wallet.descriptor(), false),
+        request(wallet.change_descriptor(), true),
+    ]))
+}
+
+fn specter(wallet: &SlapsWallet) -> String {
+    let devices: Vec<Value> = wallet
+        .signer_fingerprints
+        .iter()
+        .map(|fingerprint| json!({ "type": "other", "label": fingerprint.to_string() }))
+        .collect();
+    pretty(json!({
+        "label": &*wallet.name,
+        "blockheight": 0,
+        "descriptor": wallet.descriptor(),
+        "devices": devices,
+    }))
+}
+
+/// Electrum picks the script type from the SLIP-132 version of each xpub.
+#[derive(Clone, Copy)]
+enum ElectrumScript {
+    P2pkh,
+    P2wpkhP2sh,
+    P2wpkh,
+    P2wsh,
+}
+
+fn slip132(xpub: &ExtendedPubKey, script: ElectrumScript) -> String {
+    let mainnet = xpub.network == Network::Bitcoin;
+    let version: [u8; 4] = match (script, mainnet) {
+        (ElectrumScript::P2pkh, true) => [0x04, 0x88, 0xb2, 0x1e],
+        (ElectrumScript::P2wpkhP2sh, true) => [0x04, 0x9d, 0x7c, 0xb2],
+        (ElectrumScript::P2wpkh, true) => [0x04, 0xb2, 0x47, 0x46],
+        (ElectrumScript::P2wsh, true) => [0x02, 0xaa, 0x7e, 0xd3],
+        (ElectrumScript::P2pkh, false) => [0x04, 0x35, 0x87, 0xcf],
+        (ElectrumScript::P2wpkhP2sh, false) => [0x04, 0x4a, 0x52, 0x62],
+        (ElectrumScript::P2wpkh, false) => [0x04, 0x5f, 0x1c, 0xf6],
+        (ElectrumScript::P2wsh, false) => [0x02, 0x57, 0x54, 0x83],
+    };
+    let mut data = xpub.encode().to_vec();
+    data[..4].copy_from_slice(&version);
+    base58::check_encode_slice(&data)
+}
+
+fn keystore(key: &SlapsSigner, script: ElectrumScript) -> Value {
+    json!({
+        "type": "bip32",
+        "xpub": slip132(&key.xpub, script),
+        "derivation": key.derivation_path.to_string(),
+        "root_fingerprint": key.fingerprint.to_string(),
+        "label": "",
+    })
+}
+
+fn electrum(wallet: &SlapsWallet) -> Result<String, ExportError> {
+    let mut file = json!({
+        "seed_version": 17,
+        "use_encryption": false,
+    });
+
+    if let Some(multisig) = &wallet.multisig {
+        file["wallet_type"] = json!(format!("{}of{}", multisig.threshold, multisig.keys.len()));
+        for (index, key) in multisig.keys.iter().enumerate() {
+            file[format!("x{}/", index + 1)] = keystore(key, ElectrumScript::P2wsh);
+        }
+        return Ok(pretty(file));
+    }
+
+    let unsupported = || ExportError::Unsupported(ExportFormat::Electrum);
+    let script = match wallet.script_type().ok_or_else(unsupported)? {
+        ScriptType::Pkh => ElectrumScript::P2pkh,
+        ScriptType::ShWpkh => ElectrumScript::P2wpkhP2sh,
+        ScriptType::Wpkh => ElectrumScript::P2wpkh,
+    };
+    let key = descriptor::single_key(wallet.descriptor()).ok_or_else(unsupported)?;
+    file["wallet_type"] = json!("standard");
+    file["keystore"] = keystore(&key, script);
+    Ok(pretty(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bdk::bitcoin::util::bip32::{DerivationPath, Fingerprint};
+
+    use super::*;
+    use crate::{descriptor::DescriptorBuilder, import};
+
+    // The BIP32 test vector 1 and 2 master keys and the vector 1 m/0' key, with their
+    // fingerprints
+    const KEYS: [(&str, &str, &str); 3] = [
+        (
+            "3442193e",
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+            "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp",
+        ),
+        (
+            "bd16bee5",
+            "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB",
+            "tpubD6NzVbkrYhZ4XJDrzRvuxHEyQaPd1mwwdDofEJwekX18tAdsqeKfxss79AJzg1431FybXg5rfpTrJF4iAhyR7RubberdzEQXiRmXGADH2eA",
+        ),
+        (
+            "5c1bd648",
+            "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+            "tpubD8eQVK4Kdxg3gHrF62jGP7dKVCoYiEB8dFSpuTawkL5YxTus5j5pf83vaKnii4bc6v2NVEy81P2gYrJczYne3QNNwMTS53p5uzDyHvnw2jm",
+        ),
+    ];
+
+    fn signer(key: (&str, &str, &str), network: Network, path: DerivationPath) -> SlapsSigner {
+        let xpub = if network == Network::Bitcoin { key.1 } else { key.2 };
+        SlapsSigner::new(
+            Fingerprint::from_str(key.0).unwrap(),
+            path,
+            ExtendedPubKey::from_str(xpub).unwrap(),
+        )
+    }
+
+    fn single_sig(script_type: ScriptType, network: Network) -> SlapsWallet {
+        let key = signer(KEYS[0], network, script_type.account_path(network, 0));
+        let builder = DescriptorBuilder::new(script_type, key);
+        let descriptor = builder.clone().build().unwrap();
+        let change_descriptor = builder.change(true).build().unwrap();
+        SlapsWallet::new_from_descriptors(&descriptor, &change_descriptor, network).unwrap()
+    }
+
+    fn multisig(network: Network) -> SlapsWallet {
+        let keys: Vec<SlapsSigner> = KEYS
+            .iter()
+            .map(|key| signer(*key, network, descriptor::multisig_account_path(network, 0)))
+            .collect();
+        SlapsWallet::new_multisig(2, &keys).unwrap().with_name("Multisig")
+    }
+
+    fn electrum_file(wallet: &SlapsWallet) -> Value {
+        serde_json::from_str(&export(wallet, ExportFormat::Electrum).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn electrum_uses_slip132_versions() {
+        let cases = [
+            (Network::Bitcoin, ScriptType::Pkh, KEYS[0].1),
+            (Network::Bitcoin, ScriptType::ShWpkh, "ypub6QqdH2c5z7967BioGSfAWFHM1EHzHPBZK7wrND3ZpEWFtzmCqvsD1bgpaE6pSAPkiSKhkuWPCJV6mZTSNMd2tK8xYTcJ48585pZecmSUzWp"),
+            (Network::Bitcoin, ScriptType::Wpkh, "zpub6jftahH18ngZxUuv6oSniLNrBCSSE1B4EEU59bwTCEt8x6aS6b2mdfLxbS4QS53g85SWWP6wexqeer516433gYpZQoJie2tcMYdJ1SYYYAL"),
+            (Network::Testnet, ScriptType::Pkh, KEYS[0].2),
+            (Network::Testnet, ScriptType::ShWpkh, "upub57Wa4MvRPNyAhzxKw1WfftuLKMiCWuDZefryEdU2JCzjgbWHqJCxXM4GVQGUSXn55srUm189Mf4uER1BVZxyhNQZ56pbiUoAzvK54VEYrWu"),
+            (Network::Testnet, ScriptType::Wpkh, "vpub5SLqN2bLY4WeZJ9SmNJHsyzqVKreTXD4ZnPC22MugDNcjhKX5xNX9QiQWcE4SSRzVWyHWUihpKRT7hckDGNzVc69wSX2JPcfGeNiT5c2XZy"),
+        ];
+        for (network, script_type, xpub) in cases.iter() {
+            let file = electrum_file(&single_sig(*script_type, *network));
+            assert_eq!(file["wallet_type"], "standard");
+            assert_eq!(file["keystore"]["xpub"], *xpub);
+            assert_eq!(file["keystore"]["root_fingerprint"], "3442193e");
+            assert_eq!(
+                file["keystore"]["derivation"],
+                script_type.account_path(*network, 0).to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn electrum_multisig_uses_p2wsh_versions() {
+        let file = electrum_file(&multisig(Network::Bitcoin));
+        assert_eq!(file["wallet_type"], "2of3");
+        assert_eq!(file["x1/"]["xpub"], "Zpub6vZyhw1ShkEwP45J3TumYQietzUhSMreYW7k4sCza1iYaH9LrzR3inCtQ91szWGaMYWVNy74YBE9n1gmPHBzq2wEFGR83SMcFGuAbGkfiwg");
+        assert_eq!(file["x2/"]["xpub"], "Zpub6vZyhw1ShkEwNfad9cz3zcSR9Bd87z7UE5uUMowpYPqQsGAY39Bz9gNUkF6rhkdYG6kHHdmDwFjQVoQzWU79xwwB7CcasvrGZeaGsE5JMmK");
+        assert_eq!(file["x3/"]["xpub"], "Zpub6xqPheJuo1MvXfD1FDnQRSpmDp33pSLfE7Ye2xb7YCupwZSXHDx8qvZJBQaajpB7Mko4FCeVGpJEkQeuLJvNtvPxSuDNxkFpmD2itxTfBFT");
+        assert_eq!(file["x3/"]["root_fingerprint"], "5c1bd648");
+        assert_eq!(file["x3/"]["derivation"], "m/48'/0'/0'/2'");
+
+        let file = electrum_file(&multisig(Network::Testnet));
+        assert_eq!(file["x1/"]["xpub"], "Vpub5dEvVGKn7251ysJpi2mGi4LeD7tufstet42rwHdT3zD2MstRrMkoEXaLKKBXzsetiz3GP4iphXoxEsEWWVXwe6CpmudRho5fANeb32XJ5a2");
+        assert_eq!(file["x2/"]["xpub"], "Vpub5dEvVGKn7251yUp9pBqZAG4QTK3LMW9UZdpbEENH2NKterud2WXjfRjvfRGWi81rdYH4HjNz6cKCxexjdgT6n1CmdqptYHaKUkKhJvyS6TV");
+        assert_eq!(file["x3/"]["xpub"], "Vpub5fWLUydFCHC18USXundub6SkXwTG3xNfZfTkuP1a2BQJjABcGbHtMfvk6akEkBZRjCKqFJGFSAt3DGCeTXGKhyfYyYRgd6ysgJn9LdFqB3P");
+    }
+
+    #[test]
+    fn coldcard_file_lists_policy_and_keys() {
+        let file = export(&multisig(Network::Bitcoin), ExportFormat::Coldcard).unwrap();
+        let expected = format!(
+            "# Coldcard Multisig setup file (created by Slaps Roof Of Wallet)\n\
+             #\n\
+             Name: Multisig\n\
+             Policy: 2 of 3\n\
+             Format: P2WSH\n\
+             \n\
+             Derivation: m/48'/0'/0'/2'\n\
+             3442193E: {}\n\
+             \n\
+             Derivation: m/48'/0'/0'/2'\n\
+             BD16BEE5: {}\n\
+             \n\
+             Derivation: m/48'/0'/0'/2'\n\
+             5C1BD648: {}\n",
+            KEYS[0].1, KEYS[1].1, KEYS[2].1
+        );
+        assert_eq!(file, expected);
+
+        assert!(matches!(
+            export(&single_sig(ScriptType::Wpkh, Network::Bitcoin), ExportFormat::Coldcard),
+            Err(ExportError::Unsupported(ExportFormat::Coldcard))
+        ));
+    }
+
+    #[test]
+    fn bitcoin_core_requests_have_checksums_and_the_internal_flag() {
+        let wallet = single_sig(ScriptType::Wpkh, Network::Bitcoin);
+        let file = export(&wallet, ExportFormat::BitcoinCore).unwrap();
+        let file: Value = serde_json::from_str(&file).unwrap();
+        let expected = json!([
+            {
+                "desc": format!("wpkh([3442193e/84'/0'/0']{}/0/*)#65hsgkeq", KEYS[0].1),
+                "active": true,
+                "internal": false,
+                "timestamp": 0,
+                "range": [0, 999],
+            },
+            {
+                "desc": format!("wpkh([3442193e/84'/0'/0']{}/1/*)#tqj34rfc", KEYS[0].1),
+                "active": true,
+                "internal": true,
+                "timestamp": 0,
+                "range": [0, 999],
+            },
+        ]);
+        assert_eq!(file, expected);
+
+        let file = export(&multisig(Network::Bitcoin), ExportFormat::BitcoinCore).unwrap();
+        let file: Value = serde_json::from_str(&file).unwrap();
+        assert!(file[0]["desc"].as_str().unwrap().ends_with("))#ner7r5mr"));
+        assert!(file[1]["desc"].as_str().unwrap().ends_with("))#kvgyajtt"));
+        assert_eq!(file[1]["internal"], true);
+    }
+
+    #[test]
+    fn bitcoin_core_export_imports_back() {
+        let wallet = single_sig(ScriptType::ShWpkh, Network::Testnet);
+        let requests = export(&wallet, ExportFormat::BitcoinCore).unwrap();
+        let requests: Value = serde_json::from_str(&requests).unwrap();
+        // `listdescriptors` wraps the same entries
+        let listed = json!({ "descriptors": requests }).to_string();
+        let imported = import::import(&listed, Network::Testnet).unwrap();
+        assert_eq!(imported.descriptor(), wallet.descriptor());
+        assert_eq!(imported.change_descriptor(), wallet.change_descriptor());
+    }
+
+    #[test]
+    fn specter_export_imports_back() {
+        let wallet = multisig(Network::Testnet);
+        let file = export(&wallet, ExportFormat::Specter).unwrap();
+        let value: Value = serde_json::from_str(&file).unwrap();
+        assert_eq!(value["label"], "Multisig");
+        assert_eq!(value["devices"].as_array().unwrap().len(), 3);
+
+        let imported = import::import(&file, Network::Regtest).unwrap();
+        assert_eq!(imported.descriptor(), wallet.descriptor());
+        assert_eq!(imported.change_descriptor(), wallet.change_descriptor());
+        assert_eq!(imported.multisig, wallet.multisig);
+    }
+}
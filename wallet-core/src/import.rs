@@ -0,0 +1,248 @@
+//! Wallets from other coordinators' exports: Bitcoin Core `listdescriptors`, Specter or
+//! Sparrow wallet JSON and Coldcard's generic JSON export.
+
+use std::{fmt, str::FromStr};
+
+use bdk::bitcoin::{
+    util::bip32::{ChildNumber, DerivationPath},
+    Network,
+};
+use bdk::{descriptor::Descriptor, miniscript::DescriptorPublicKey};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    descriptor::{self, DescriptorBuilder, DescriptorError, ScriptType, SlapsSigner},
+    SlapsWallet,
+};
+
+#[derive(Debug)]
+pub enum ImportError {
+    Json(serde_json::Error),
+    UnknownFormat,
+    /// The file has no descriptor we can use, e.g. only taproot ones
+    NoDescriptor,
+    WrongNetwork,
+    Descriptor(DescriptorError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Json(err) => write!(f, "Not a wallet file: {}", err),
+            ImportError::UnknownFormat => write!(f, "Unrecognized wallet file"),
+            ImportError::NoDescriptor => write!(f, "No supported descriptor in the wallet file"),
+            ImportError::WrongNetwork => write!(f, "Wallet file is for a different network"),
+            ImportError::Descriptor(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        ImportError::Json(err)
+    }
+}
+
+impl From<DescriptorError> for ImportError {
+    fn from(err: DescriptorError) -> Self {
+        match err {
+            DescriptorError::WrongNetwork => ImportError::WrongNetwork,
+            err => ImportError::Descriptor(err),
+        }
+    }
+}
+
+/// Reads any of the supported formats, telling them apart by their fields. Every key has to
+/// be on `network`, which `SlapsWallet::new_from_descriptors` checks for multisig too.
+pub fn import(contents: &str, network: Network) -> Result<SlapsWallet, ImportError> {
+    let value: Value = serde_json::from_str(contents)?;
+    if value.get("descriptors").is_some() {
+        bitcoin_core(serde_json::from_value(value)?, network)
+    } else if value.get("descriptor").is_some() {
+        specter(serde_json::from_value(value)?, network)
+    } else if value.get("xfp").is_some() {
+        coldcard(serde_json::from_value(value)?, network)
+    } else {
+        Err(ImportError::UnknownFormat)
+    }
+}
+
+#[derive(Deserialize)]
+struct CoreDescriptor {
+    desc: String,
+    #[serde(default)]
+    active: bool,
+    #[serde(default)]
+    internal: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct CoreWallet {
+    #[serde(default)]
+    wallet_name: String,
+    descriptors: Vec<CoreDescriptor>,
+}
+
+/// Core descriptor wallets have an active receive and change descriptor for every script
+/// type. We take the most modern one we support.
+fn bitcoin_core(file: CoreWallet, network: Network) -> Result<SlapsWallet, ImportError> {
+    let active = |script_type: ScriptType, internal: bool| {
+        file.descriptors.iter().find(|d| {
+            d.active
+                && d.internal == Some(internal)
+                && ScriptType::from_descriptor(&d.desc) == Some(script_type)
+        })
+    };
+
+    for script_type in [ScriptType::Wpkh, ScriptType::ShWpkh, ScriptType::Pkh].iter() {
+        if let (Some(receive), Some(change)) = (active(*script_type, false), active(*script_type, true)) {
+            let wallet = SlapsWallet::new_from_descriptors(&receive.desc, &change.desc, network)?;
+            return Ok(wallet.with_name(&file.wallet_name));
+        }
+    }
+
+    // Watch-only Core wallets, like a multisig imported with importdescriptors
+    let receive = file
+        .descriptors
+        .iter()
+        .find(|d| d.active && d.internal != Some(true))
+        .ok_or(ImportError::NoDescriptor)?;
+    let change = file
+        .descriptors
+        .iter()
+        .find(|d| d.active && d.internal == Some(true))
+        .map(|d| d.desc.clone())
+        .map_or_else(|| change_descriptor(&receive.desc), Ok)?;
+    let wallet = SlapsWallet::new_from_descriptors(&receive.desc, &change, network)?;
+    Ok(wallet.with_name(&file.wallet_name))
+}
+
+#[derive(Deserialize)]
+struct SpecterWallet {
+    #[serde(default)]
+    label: String,
+    descriptor: String,
+}
+
+fn specter(file: SpecterWallet, network: Network) -> Result<SlapsWallet, ImportError> {
+    let descriptor = descriptor::check_checksum(&file.descriptor)?;
+    // Newer Sparrow exports a single descriptor with both branches as `<0;1>`
+    let receive = descriptor.replace("<0;1>", "0");
+    let change = change_descriptor(&receive)?;
+    let wallet = SlapsWallet::new_from_descriptors(&receive, &change, network)?;
+    Ok(wallet.with_name(&file.label))
+}
+
+/// The change descriptor that goes with a receive descriptor whose keys all end in `/0/*`.
+fn change_descriptor(receive: &str) -> Result<String, ImportError> {
+    let receive = Descriptor::<DescriptorPublicKey>::from_str(descriptor::check_checksum(receive)?)
+        .map_err(DescriptorError::from)?;
+    let change = receive.translate_pk(change_key, change_key)?;
+    Ok(descriptor::with_checksum(&change.to_string())?)
+}
+
+/// Moves a key from the receive branch to the change branch by replacing its last step.
+fn change_key(key: &DescriptorPublicKey) -> Result<DescriptorPublicKey, ImportError> {
+    let mut xkey = match key {
+        DescriptorPublicKey::XPub(xkey) if xkey.is_wildcard => xkey.clone(),
+        _ => return Err(ImportError::NoDescriptor),
+    };
+    let mut path = xkey.derivation_path.as_ref().to_vec();
+    match path.last_mut() {
+        Some(last) if *last == ChildNumber::Normal { index: 0 } => {
+            *last = ChildNumber::Normal { index: 1 };
+        }
+        _ => return Err(ImportError::NoDescriptor),
+    }
+    xkey.derivation_path = DerivationPath::from(path);
+    Ok(DescriptorPublicKey::XPub(xkey))
+}
+
+#[derive(Deserialize)]
+struct ColdcardAccount {
+    xpub: String,
+    deriv: String,
+}
+
+#[derive(Deserialize)]
+struct ColdcardWallet {
+    #[serde(default)]
+    chain: String,
+    xfp: String,
+    bip44: Option<ColdcardAccount>,
+    bip49: Option<ColdcardAccount>,
+    bip84: Option<ColdcardAccount>,
+}
+
+fn coldcard(file: ColdcardWallet, network: Network) -> Result<SlapsWallet, ImportError> {
+    let mainnet = file.chain == "BTC";
+    if mainnet != (network == Network::Bitcoin) {
+        return Err(ImportError::WrongNetwork);
+    }
+
+    let (script_type, account) = match (&file.bip84, &file.bip49, &file.bip44) {
+        (Some(account), _, _) => (ScriptType::Wpkh, account),
+        (None, Some(account), _) => (ScriptType::ShWpkh, account),
+        (None, None, Some(account)) => (ScriptType::Pkh, account),
+        (None, None, None) => return Err(ImportError::NoDescriptor),
+    };
+    let origin = account.deriv.replacen("m", &file.xfp.to_lowercase(), 1);
+    let key: SlapsSigner = format!("[{}]{}", origin, account.xpub).parse()?;
+
+    let builder = DescriptorBuilder::new(script_type, key);
+    let wallet =
+        SlapsWallet::new_from_descriptors(&builder.clone().build()?, &builder.change(true).build()?, network)?;
+    Ok(wallet.with_name(&format!("Coldcard {}", file.xfp)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TPUB: &str = "[2de67592/48'/1'/0'/2']tpubDFFgV6kiSbSwMDKhketWTo3hRuvdxCuK7Xzc4MW1joKTV9wGrp5Xbsvk79QhU3qNzkijKSvo18JzzdEfBx9QHWH3cqLYiQAKParawyXQH3k";
+    // The BIP32 test vector 1 master key
+    const XPUB: &str = "[3442193e/48'/0'/0'/2']xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+    fn specter_file(descriptor: &str) -> String {
+        serde_json::json!({ "label": "Imported", "descriptor": descriptor }).to_string()
+    }
+
+    #[test]
+    fn change_descriptor_replaces_the_last_step() {
+        let receive = format!("wpkh({}/0/0/*)", TPUB);
+        let expected = descriptor::with_checksum(&format!("wpkh({}/0/1/*)", TPUB)).unwrap();
+        assert_eq!(change_descriptor(&receive).unwrap(), expected);
+
+        let receive = format!("wpkh({}/1/*)", TPUB);
+        assert!(matches!(change_descriptor(&receive), Err(ImportError::NoDescriptor)));
+    }
+
+    #[test]
+    fn specter_checksum_is_checked() {
+        let descriptor = format!("wpkh({}/0/*)", TPUB);
+        let with_checksum = descriptor::with_checksum(&descriptor).unwrap();
+        assert!(import(&specter_file(&with_checksum), Network::Regtest).is_ok());
+
+        let tampered = format!("{}#00000000", descriptor);
+        assert!(matches!(
+            import(&specter_file(&tampered), Network::Regtest),
+            Err(ImportError::Descriptor(DescriptorError::InvalidChecksum { .. }))
+        ));
+    }
+
+    #[test]
+    fn multisig_keys_must_match_the_network() {
+        let descriptor = format!("wsh(sortedmulti(1,{}/0/*,{}/0/*))", TPUB, XPUB);
+        assert!(matches!(
+            import(&specter_file(&descriptor), Network::Regtest),
+            Err(ImportError::WrongNetwork)
+        ));
+        assert!(matches!(
+            import(&specter_file(&descriptor), Network::Bitcoin),
+            Err(ImportError::WrongNetwork)
+        ));
+    }
+}
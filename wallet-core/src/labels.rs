@@ -0,0 +1,185 @@
+//! Wallet labels, imported and exported in the BIP329 JSON lines format.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug)]
+pub enum LabelError {
+    Json { line: usize, err: serde_json::Error },
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelError::Json { line, err } => write!(f, "Invalid label on line {}: {}", line, err),
+        }
+    }
+}
+
+impl std::error::Error for LabelError {}
+
+/// What a label's `ref` points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelType {
+    /// A txid
+    Tx,
+    Addr,
+    Pubkey,
+    /// A `txid:vout` spent by one of our transactions
+    Input,
+    /// A `txid:vout` we received
+    Output,
+    Xpub,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+    #[serde(rename = "type")]
+    pub label_type: LabelType,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// Optional in BIP329, e.g. for records that only set `spendable`
+    #[serde(default)]
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spendable: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LabelStore {
+    labels: BTreeMap<(LabelType, String), Label>,
+}
+
+impl LabelStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, label_type: LabelType, reference: &str) -> Option<&str> {
+        self.labels
+            .get(&(label_type, reference.to_string()))
+            .map(|label| label.label.as_str())
+    }
+
+    /// Sets or replaces a label, an empty label removes it.
+    pub fn set(&mut self, label_type: LabelType, reference: &str, label: &str) {
+        let key = (label_type, reference.to_string());
+        if label.is_empty() {
+            self.labels.remove(&key);
+            return;
+        }
+        let entry = self.labels.entry(key).or_insert_with(|| Label {
+            label_type,
+            reference: reference.to_string(),
+            label: String::new(),
+            origin: None,
+            spendable: None,
+        });
+        entry.label = label.to_string();
+    }
+
+    pub fn insert(&mut self, label: Label) {
+        self.labels
+            .insert((label.label_type, label.reference.clone()), label);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Label> {
+        self.labels.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Merges BIP329 labels into the store, replacing labels for the same reference. Lines
+    /// with types from newer versions of the BIP are skipped as it asks. Returns how many
+    /// labels were imported.
+    pub fn import_jsonl(&mut self, contents: &str) -> Result<usize, LabelError> {
+        let mut labels = vec![];
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let json = |err| LabelError::Json { line: index + 1, err };
+            let value: Value = serde_json::from_str(line).map_err(json)?;
+            if serde_json::from_value::<LabelType>(value["type"].clone()).is_err() {
+                continue;
+            }
+            labels.push(serde_json::from_value::<Label>(value).map_err(json)?);
+        }
+
+        // Only change the store once the whole file parsed
+        let imported = labels.len();
+        for label in labels {
+            self.insert(label);
+        }
+        Ok(imported)
+    }
+
+    pub fn export_jsonl(&self) -> String {
+        self.labels
+            .values()
+            .map(|label| serde_json::to_string(label).expect("Labels always serialize") + "\n")
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd";
+
+    #[test]
+    fn round_trips_and_skips_unknown_types() {
+        let mut store = LabelStore::new();
+        store.set(LabelType::Tx, TXID, "Rent");
+        store.set(LabelType::Output, &format!("{}:1", TXID), "Change");
+        store.insert(Label {
+            label_type: LabelType::Output,
+            reference: format!("{}:0", TXID),
+            label: String::new(),
+            origin: None,
+            spendable: Some(false),
+        });
+
+        let mut exported = store.export_jsonl();
+        exported.push_str(&format!("{{\"type\":\"future\",\"ref\":\"{}\",\"label\":\"Skipped\"}}\n", TXID));
+        exported.push('\n');
+
+        let mut imported = LabelStore::new();
+        assert_eq!(imported.import_jsonl(&exported).unwrap(), 3);
+        assert_eq!(imported, store);
+        assert_eq!(imported.get(LabelType::Tx, TXID), Some("Rent"));
+    }
+
+    #[test]
+    fn label_is_optional() {
+        let line = format!("{{\"type\":\"output\",\"ref\":\"{}:0\",\"spendable\":false}}", TXID);
+        let mut store = LabelStore::new();
+        assert_eq!(store.import_jsonl(&line).unwrap(), 1);
+        let label = store.iter().next().unwrap();
+        assert_eq!(label.label, "");
+        assert_eq!(label.spendable, Some(false));
+    }
+
+    #[test]
+    fn malformed_line_leaves_the_store_alone() {
+        let contents = format!(
+            "{{\"type\":\"tx\",\"ref\":\"{}\",\"label\":\"Rent\"}}\n{{\"type\":\"tx\",\"ref\":",
+            TXID
+        );
+        let mut store = LabelStore::new();
+        assert!(matches!(store.import_jsonl(&contents), Err(LabelError::Json { line: 2, .. })));
+        assert!(store.is_empty());
+    }
+}
@@ -4,8 +4,25 @@ pub type ArcStr = Arc<str>;
 mod wallet;
 mod devices;
 mod signer;
+pub mod psbt;
+pub mod psbt_qr;
+pub mod descriptor;
+pub mod bsms;
+pub mod backup;
+pub mod export;
+pub mod import;
+pub mod labels;
+pub mod address;
+pub mod address_book;
+pub mod bip21;
+mod registry;
+mod storage;
 
-pub use wallet::SlapsWallet;
-pub use devices::{SlapsDevices, SlapsDevice};
-pub use signer::HWISigner;
+pub use wallet::{HistoryItem, SlapsWallet, UtxoItem, WalletError};
+pub use devices::{DeviceError, RegistrationStatus, SlapsDevices, SlapsDevice};
+pub use signer::{HWISigner, HWISignerError};
+pub use psbt::{ImportedPsbt, PsbtVersion};
+pub use registry::{default_registry_path, MultisigEntry, RegistryError, WalletEntry, WalletRegistry};
+pub use storage::{Storage, StorageError};
+pub use descriptor::{DescriptorBuilder, DescriptorError, Multisig, ScriptType, SlapsSigner};
 
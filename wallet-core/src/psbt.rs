@@ -0,0 +1,774 @@
+use std::fmt;
+
+use bdk::bitcoin::{
+    blockdata::script::Instruction,
+    consensus::{encode, serialize},
+    hashes::hex::{FromHex, ToHex},
+    secp256k1::{Message, Secp256k1, Signature, Verification},
+    util::{bip143::SigHashCache, bip32::Fingerprint, psbt},
+    Address, Network, OutPoint, PublicKey, Script, SigHashType, Txid,
+};
+
+pub use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+
+mod v2;
+
+#[derive(Debug)]
+pub enum PsbtError {
+    Base64(base64::DecodeError),
+    Hex(bdk::bitcoin::hashes::hex::Error),
+    Encode(encode::Error),
+    Psbt(psbt::Error),
+    Finalize(bdk::miniscript::psbt::Error),
+    NothingToCombine,
+    MissingUtxo(usize),
+    InvalidUtxo(usize),
+    NegativeFee,
+    /// The input or output amounts add up to more than fits in a u64
+    AmountOverflow,
+    InvalidMagic,
+    Truncated,
+    UnsupportedVersion(u32),
+    MissingV2Field(&'static str),
+    ConflictingLocktimes,
+    InvalidLocktime,
+    /// A field the PSBT's version doesn't allow
+    UnexpectedField(&'static str),
+    NegativeAmount(usize),
+    TransactionChanged,
+    OutputChanged(usize),
+    InputChanged(usize),
+    MissingSignature(usize),
+    InvalidSignature(usize),
+    WrongSigHash(usize),
+    /// None of the wallet's signers signed every input they have a key for
+    NotSigned,
+}
+
+impl fmt::Display for PsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PsbtError::Base64(err) => write!(f, "Invalid base64: {}", err),
+            PsbtError::Hex(err) => write!(f, "Invalid hex: {}", err),
+            PsbtError::Encode(err) => write!(f, "Invalid PSBT: {}", err),
+            PsbtError::Psbt(err) => write!(f, "PSBT error: {}", err),
+            PsbtError::Finalize(err) => write!(f, "Failed to finalize PSBT: {}", err),
+            PsbtError::NothingToCombine => write!(f, "No PSBTs to combine"),
+            PsbtError::MissingUtxo(index) => write!(f, "Input {} has no UTXO information", index),
+            PsbtError::InvalidUtxo(index) => {
+                write!(f, "Input {} has a UTXO that doesn't match its outpoint", index)
+            }
+            PsbtError::NegativeFee => write!(f, "Outputs spend more than the inputs provide"),
+            PsbtError::AmountOverflow => {
+                write!(f, "Amounts add up to more than any transaction can hold")
+            }
+            PsbtError::InvalidMagic => write!(f, "Not a PSBT: missing magic bytes"),
+            PsbtError::Truncated => write!(f, "PSBT ended unexpectedly"),
+            PsbtError::UnsupportedVersion(version) => {
+                write!(f, "PSBT version {} is not supported", version)
+            }
+            PsbtError::MissingV2Field(field) => write!(f, "PSBTv2 is missing the {}", field),
+            PsbtError::ConflictingLocktimes => {
+                write!(f, "Inputs require both a height and a time based locktime")
+            }
+            PsbtError::InvalidLocktime => write!(f, "Input requires an out of range locktime"),
+            PsbtError::UnexpectedField(field) => {
+                write!(f, "PSBT has a {} field its version doesn't allow", field)
+            }
+            PsbtError::NegativeAmount(index) => write!(f, "Output {} has a negative amount", index),
+            PsbtError::TransactionChanged => write!(f, "The transaction was modified"),
+            PsbtError::OutputChanged(index) => write!(f, "Output {} was modified", index),
+            PsbtError::InputChanged(index) => write!(f, "Input {} was modified", index),
+            PsbtError::MissingSignature(index) => write!(f, "Input {} wasn't signed", index),
+            PsbtError::NotSigned => write!(f, "None of the wallet's signers signed it"),
+            PsbtError::InvalidSignature(index) => {
+                write!(f, "Input {} has an invalid signature", index)
+            }
+            PsbtError::WrongSigHash(index) => {
+                write!(f, "Input {} was signed with an unexpected sighash type", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PsbtError {}
+
+impl From<base64::DecodeError> for PsbtError {
+    fn from(err: base64::DecodeError) -> Self {
+        PsbtError::Base64(err)
+    }
+}
+
+impl From<bdk::bitcoin::hashes::hex::Error> for PsbtError {
+    fn from(err: bdk::bitcoin::hashes::hex::Error) -> Self {
+        PsbtError::Hex(err)
+    }
+}
+
+impl From<encode::Error> for PsbtError {
+    fn from(err: encode::Error) -> Self {
+        PsbtError::Encode(err)
+    }
+}
+
+impl From<psbt::Error> for PsbtError {
+    fn from(err: psbt::Error) -> Self {
+        PsbtError::Psbt(err)
+    }
+}
+
+impl From<bdk::miniscript::psbt::Error> for PsbtError {
+    fn from(err: bdk::miniscript::psbt::Error) -> Self {
+        PsbtError::Finalize(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PsbtVersion {
+    V0,
+    V2,
+}
+
+/// A PSBT decoded from either version, remembering the version it arrived in so it can be
+/// handed back in the same format once reviewed and signed.
+#[derive(Debug, Clone)]
+pub struct ImportedPsbt {
+    pub psbt: PartiallySignedTransaction,
+    pub version: PsbtVersion,
+}
+
+impl ImportedPsbt {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PsbtError> {
+        let version = match v2::version(bytes)? {
+            0 => PsbtVersion::V0,
+            2 => PsbtVersion::V2,
+            version => return Err(PsbtError::UnsupportedVersion(version)),
+        };
+        Ok(Self {
+            psbt: v2::to_v0(bytes)?,
+            version,
+        })
+    }
+
+    pub fn from_base64(b64: &str) -> Result<Self, PsbtError> {
+        Self::from_bytes(&base64::decode(b64.trim())?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PsbtError> {
+        to_bytes_as(&self.psbt, self.version)
+    }
+
+    pub fn to_base64(&self) -> Result<String, PsbtError> {
+        Ok(base64::encode(&self.to_bytes()?))
+    }
+}
+
+/// Decodes a PSBT of either version. v2 PSBTs are converted to v0.
+pub fn from_bytes(bytes: &[u8]) -> Result<PartiallySignedTransaction, PsbtError> {
+    Ok(ImportedPsbt::from_bytes(bytes)?.psbt)
+}
+
+pub fn to_bytes_as(
+    psbt: &PartiallySignedTransaction,
+    version: PsbtVersion,
+) -> Result<Vec<u8>, PsbtError> {
+    match version {
+        PsbtVersion::V0 => Ok(to_bytes(psbt)),
+        PsbtVersion::V2 => v2::from_v0(psbt),
+    }
+}
+
+pub fn to_bytes(psbt: &PartiallySignedTransaction) -> Vec<u8> {
+    serialize(psbt)
+}
+
+pub fn from_base64(b64: &str) -> Result<PartiallySignedTransaction, PsbtError> {
+    let bytes = base64::decode(b64.trim())?;
+    from_bytes(&bytes)
+}
+
+pub fn to_base64(psbt: &PartiallySignedTransaction) -> String {
+    base64::encode(&to_bytes(psbt))
+}
+
+pub fn from_hex(hex: &str) -> Result<PartiallySignedTransaction, PsbtError> {
+    let bytes = Vec::<u8>::from_hex(hex.trim())?;
+    from_bytes(&bytes)
+}
+
+pub fn to_hex(psbt: &PartiallySignedTransaction) -> String {
+    to_bytes(psbt).to_hex()
+}
+
+/// Merges the signatures and metadata of several PSBTs for the same transaction into one.
+pub fn combine(
+    psbts: Vec<PartiallySignedTransaction>,
+) -> Result<PartiallySignedTransaction, PsbtError> {
+    let mut psbts = psbts.into_iter();
+    let mut combined = psbts.next().ok_or(PsbtError::NothingToCombine)?;
+    for psbt in psbts {
+        combined.merge(psbt)?;
+    }
+    Ok(combined)
+}
+
+/// Finalizes every input using the miniscript satisfier, without needing a `Wallet`.
+pub fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), PsbtError> {
+    let secp = Secp256k1::verification_only();
+    bdk::miniscript::psbt::finalize(psbt, &secp)?;
+    Ok(())
+}
+
+pub fn is_finalized(psbt: &PartiallySignedTransaction) -> bool {
+    psbt.inputs
+        .iter()
+        .all(|input| input.final_script_sig.is_some() || input.final_script_witness.is_some())
+}
+
+pub fn input_value(psbt: &PartiallySignedTransaction, index: usize) -> Result<u64, PsbtError> {
+    let input = &psbt.inputs[index];
+    let outpoint = psbt.global.unsigned_tx.input[index].previous_output;
+
+    if let Some(utxo) = &input.witness_utxo {
+        return Ok(utxo.value);
+    }
+
+    match &input.non_witness_utxo {
+        Some(tx) if tx.txid() == outpoint.txid => tx
+            .output
+            .get(outpoint.vout as usize)
+            .map(|output| output.value)
+            .ok_or(PsbtError::InvalidUtxo(index)),
+        Some(_) => Err(PsbtError::InvalidUtxo(index)),
+        None => Err(PsbtError::MissingUtxo(index)),
+    }
+}
+
+pub fn fee(psbt: &PartiallySignedTransaction) -> Result<u64, PsbtError> {
+    let mut inputs: u64 = 0;
+    for index in 0..psbt.inputs.len() {
+        inputs = inputs
+            .checked_add(input_value(psbt, index)?)
+            .ok_or(PsbtError::AmountOverflow)?;
+    }
+    let outputs = psbt
+        .global
+        .unsigned_tx
+        .output
+        .iter()
+        .try_fold(0u64, |total, output| total.checked_add(output.value))
+        .ok_or(PsbtError::AmountOverflow)?;
+
+    inputs.checked_sub(outputs).ok_or(PsbtError::NegativeFee)
+}
+
+/// Fee rate in sat/vbyte. Until the PSBT is finalized this is based on the unsigned
+/// transaction, so it overestimates the rate that will actually be paid.
+pub fn fee_rate(psbt: &PartiallySignedTransaction) -> Result<f32, PsbtError> {
+    let fee = fee(psbt)?;
+    let weight = psbt.clone().extract_tx().get_weight();
+    let vbytes = (weight as f32 / 4.0).ceil();
+    Ok(fee as f32 / vbytes)
+}
+
+/// Checks a PSBT signed somewhere we can't ask for the signer's fingerprint, like an
+/// air-gapped QR signer, and returns which of the wallet's `signers` signed all their inputs.
+/// Only our own PSBT says which keys belong to whom, the signed one could claim anything.
+pub fn verify_cosigned(
+    original: &PartiallySignedTransaction,
+    signed: &PartiallySignedTransaction,
+    signers: &[Fingerprint],
+) -> Result<Vec<Fingerprint>, PsbtError> {
+    check_unchanged(original, signed)?;
+
+    // Every signature that was added has to be valid, even from a signer that didn't finish
+    let secp = Secp256k1::verification_only();
+    for (index, input) in signed.inputs.iter().enumerate() {
+        for (key, sig) in &input.partial_sigs {
+            verify_signature(original, index, key, sig, &secp)?;
+        }
+    }
+
+    let mut signed_by = vec![];
+    for fingerprint in signers {
+        let has_keys = original
+            .inputs
+            .iter()
+            .flat_map(|input| input.hd_keypaths.values())
+            .any(|(key_fingerprint, _)| key_fingerprint == fingerprint);
+        if !has_keys {
+            continue;
+        }
+        match verify_signed(original, signed, *fingerprint) {
+            Ok(()) => signed_by.push(*fingerprint),
+            Err(PsbtError::MissingSignature(_)) => (),
+            Err(err) => return Err(err),
+        }
+    }
+    if signed_by.is_empty() {
+        return Err(PsbtError::NotSigned);
+    }
+    Ok(signed_by)
+}
+
+/// Checks that a PSBT returned by a signer is the one we sent, with a valid signature for every
+/// key of `fingerprint` on each input.
+pub fn verify_signed(
+    original: &PartiallySignedTransaction,
+    signed: &PartiallySignedTransaction,
+    fingerprint: Fingerprint,
+) -> Result<(), PsbtError> {
+    check_unchanged(original, signed)?;
+
+    let secp = Secp256k1::verification_only();
+    for (index, input) in signed.inputs.iter().enumerate() {
+        let our_keys = original.inputs[index]
+            .hd_keypaths
+            .iter()
+            .filter(|(_, (key_fingerprint, _))| *key_fingerprint == fingerprint)
+            .map(|(key, _)| key);
+
+        let finalized = input.final_script_sig.is_some() || input.final_script_witness.is_some();
+        for key in our_keys {
+            if finalized {
+                // Finalizing drops the partial signatures, so ours has to be one of the pushes
+                // in the final script
+                let signed_here = final_pushes(input)
+                    .iter()
+                    .any(|push| verify_signature(original, index, key, push, &secp).is_ok());
+                if !signed_here {
+                    return Err(PsbtError::MissingSignature(index));
+                }
+            } else {
+                let sig = input
+                    .partial_sigs
+                    .get(key)
+                    .ok_or(PsbtError::MissingSignature(index))?;
+                verify_signature(original, index, key, sig, &secp)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the signer only added signatures: the transaction, the outputs' metadata and
+/// everything the inputs' signatures commit to must be as we sent them.
+fn check_unchanged(
+    original: &PartiallySignedTransaction,
+    signed: &PartiallySignedTransaction,
+) -> Result<(), PsbtError> {
+    if original.global.unsigned_tx != signed.global.unsigned_tx
+        || original.inputs.len() != signed.inputs.len()
+    {
+        return Err(PsbtError::TransactionChanged);
+    }
+
+    if original.outputs.len() != signed.outputs.len() {
+        return Err(PsbtError::TransactionChanged);
+    }
+    for (index, (before, after)) in original.outputs.iter().zip(signed.outputs.iter()).enumerate() {
+        if before.redeem_script != after.redeem_script
+            || before.witness_script != after.witness_script
+            || before.hd_keypaths != after.hd_keypaths
+        {
+            return Err(PsbtError::OutputChanged(index));
+        }
+    }
+
+    // Finalizing clears the scripts and sighash type, so only what the signer kept is compared
+    for (index, (before, after)) in original.inputs.iter().zip(signed.inputs.iter()).enumerate() {
+        if changed(&before.witness_utxo, &after.witness_utxo)
+            || changed(&before.non_witness_utxo, &after.non_witness_utxo)
+            || changed(&before.redeem_script, &after.redeem_script)
+            || changed(&before.witness_script, &after.witness_script)
+            || changed(&before.sighash_type, &after.sighash_type)
+        {
+            return Err(PsbtError::InputChanged(index));
+        }
+    }
+
+    Ok(())
+}
+
+fn changed<T: PartialEq>(before: &Option<T>, after: &Option<T>) -> bool {
+    after.is_some() && after != before
+}
+
+/// Everything pushed by a finalized input's witness and script sig.
+fn final_pushes(input: &psbt::Input) -> Vec<Vec<u8>> {
+    let mut pushes = input.final_script_witness.clone().unwrap_or_default();
+    if let Some(script_sig) = &input.final_script_sig {
+        pushes.extend(script_sig.instructions().filter_map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(bytes)) => Some(bytes.to_vec()),
+            _ => None,
+        }));
+    }
+    pushes
+}
+
+/// Verifies `sig` for `key` on input `index`. The sighash is computed from `original` alone,
+/// never from the signer's copy.
+fn verify_signature<C: Verification>(
+    original: &PartiallySignedTransaction,
+    index: usize,
+    key: &PublicKey,
+    sig: &[u8],
+    secp: &Secp256k1<C>,
+) -> Result<(), PsbtError> {
+    let input = &original.inputs[index];
+    let tx = &original.global.unsigned_tx;
+    let outpoint = tx.input[index].previous_output;
+
+    let (sighash_byte, der) = sig.split_last().ok_or(PsbtError::InvalidSignature(index))?;
+    // Anything but the type we asked for would let the rest of the transaction be changed
+    let sighash_type = input.sighash_type.unwrap_or(SigHashType::All);
+    if *sighash_byte as u32 != sighash_type.as_u32() {
+        return Err(PsbtError::WrongSigHash(index));
+    }
+    let signature = Signature::from_der(der).map_err(|_| PsbtError::InvalidSignature(index))?;
+
+    let script_pubkey = match (&input.witness_utxo, &input.non_witness_utxo) {
+        (Some(utxo), _) => utxo.script_pubkey.clone(),
+        (None, Some(prev_tx)) if prev_tx.txid() == outpoint.txid => prev_tx
+            .output
+            .get(outpoint.vout as usize)
+            .ok_or(PsbtError::InvalidUtxo(index))?
+            .script_pubkey
+            .clone(),
+        (None, Some(_)) => return Err(PsbtError::InvalidUtxo(index)),
+        (None, None) => return Err(PsbtError::MissingUtxo(index)),
+    };
+    let spent_script = input.redeem_script.as_ref().unwrap_or(&script_pubkey);
+
+    let sighash = if let Some(witness_script) = &input.witness_script {
+        SigHashCache::new(tx).signature_hash(
+            index,
+            witness_script,
+            input_value(original, index)?,
+            sighash_type,
+        )
+    } else if spent_script.is_v0_p2wpkh() {
+        // The script code for p2wpkh is the p2pkh script of the key
+        let script_code: Script = Address::p2pkh(key, Network::Bitcoin).script_pubkey();
+        SigHashCache::new(tx).signature_hash(
+            index,
+            &script_code,
+            input_value(original, index)?,
+            sighash_type,
+        )
+    } else {
+        tx.signature_hash(index, spent_script, sighash_type.as_u32())
+    };
+
+    let message = Message::from_slice(&sighash[..]).map_err(|_| PsbtError::InvalidSignature(index))?;
+    secp.verify(&message, &signature, &key.key)
+        .map_err(|_| PsbtError::InvalidSignature(index))
+}
+
+#[derive(Debug, Clone)]
+pub struct PsbtSummary {
+    pub txid: Txid,
+    pub inputs: Vec<(OutPoint, Option<u64>)>,
+    pub outputs: Vec<(String, u64)>,
+    pub fee: Option<u64>,
+    pub fee_rate: Option<f32>,
+    pub signatures: usize,
+    pub finalized: bool,
+}
+
+pub fn summary(psbt: &PartiallySignedTransaction, network: Network) -> PsbtSummary {
+    let tx = &psbt.global.unsigned_tx;
+
+    let inputs = tx
+        .input
+        .iter()
+        .enumerate()
+        .map(|(index, txin)| (txin.previous_output, input_value(psbt, index).ok()))
+        .collect();
+
+    let outputs = tx
+        .output
+        .iter()
+        .map(|output| {
+            let destination = match Address::from_script(&output.script_pubkey, network) {
+                Some(address) => address.to_string(),
+                None => output.script_pubkey.to_string(),
+            };
+            (destination, output.value)
+        })
+        .collect();
+
+    PsbtSummary {
+        txid: tx.txid(),
+        inputs,
+        outputs,
+        fee: fee(psbt).ok(),
+        fee_rate: fee_rate(psbt).ok(),
+        signatures: psbt.inputs.iter().map(|i| i.partial_sigs.len()).sum(),
+        finalized: is_finalized(psbt),
+    }
+}
+
+impl fmt::Display for PsbtSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Transaction {}", self.txid)?;
+        writeln!(f, "Inputs:")?;
+        for (outpoint, value) in &self.inputs {
+            match value {
+                Some(value) => writeln!(f, "  {} ({} sats)", outpoint, value)?,
+                None => writeln!(f, "  {} (unknown amount)", outpoint)?,
+            }
+        }
+        writeln!(f, "Outputs:")?;
+        for (destination, value) in &self.outputs {
+            writeln!(f, "  {} ({} sats)", destination, value)?;
+        }
+        match (self.fee, self.fee_rate) {
+            (Some(fee), Some(rate)) => writeln!(f, "Fee: {} sats ({:.1} sat/vB)", fee, rate)?,
+            _ => writeln!(f, "Fee: unknown")?,
+        }
+        writeln!(f, "Signatures: {}", self.signatures)?;
+        write!(f, "Finalized: {}", self.finalized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    /// Spends a 100000 sat p2wpkh coin to 60000 sats plus 39000 change, with the input key's
+    /// origin `d34db33f/84'/1'/0'/0/0`.
+    const UNSIGNED: &str = "cHNidP8BAHECAAAAARERERERERERERERERERERERERERERERERERERERERERAAAAAAD9////AmDqAAAAAAAAFgAU68DuCyq56Cd6YAwlFHXiKjJBocFYmAAAAAAAABYAFHmwAIh2JrKUqRRQGkzSJrWLI1mDAAAAAAABAR+ghgEAAAAAABYAFHmwAIh2JrKUqRRQGkzSJrWLI1mDIgYDG4TFVnsSZECZXT7VqroFZdceGDRgSBn/nBf16dXdB48Y002zP1QAAIABAACAAAAAgAAAAAAAAAAAAAAA";
+    /// `UNSIGNED` with a SIGHASH_ALL signature from the input key.
+    const SIGNED: &str = "cHNidP8BAHECAAAAARERERERERERERERERERERERERERERERERERERERERERAAAAAAD9////AmDqAAAAAAAAFgAU68DuCyq56Cd6YAwlFHXiKjJBocFYmAAAAAAAABYAFHmwAIh2JrKUqRRQGkzSJrWLI1mDAAAAAAABAR+ghgEAAAAAABYAFHmwAIh2JrKUqRRQGkzSJrWLI1mDIgIDG4TFVnsSZECZXT7VqroFZdceGDRgSBn/nBf16dXdB49IMEUCIQDlp3ZnLc2XtM6RKYFVyh2MbCJzoDK/mYmQSSbrviIEQgIgd96j3tKlULcRhTbAh0/1eXM9jJPWmaiXQGFteObJFpwBIgYDG4TFVnsSZECZXT7VqroFZdceGDRgSBn/nBf16dXdB48Y002zP1QAAIABAACAAAAAgAAAAAAAAAAAAAAA";
+
+    #[test]
+    fn base64_round_trip() {
+        let psbt = from_base64(UNSIGNED).unwrap();
+        assert_eq!(
+            psbt.global.unsigned_tx.txid().to_string(),
+            "0fe24fe111c6812a63ef6b478d286889c8fdb2516bb62461736a41b60fccc4b8"
+        );
+        assert_eq!(to_base64(&psbt), UNSIGNED);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let psbt = from_base64(SIGNED).unwrap();
+        let hex = to_hex(&psbt);
+        assert!(hex.starts_with("70736274ff"));
+        assert_eq!(to_base64(&from_hex(&hex).unwrap()), SIGNED);
+    }
+
+    #[test]
+    fn rejects_bad_encodings() {
+        assert!(matches!(from_base64("not base64!"), Err(PsbtError::Base64(_))));
+        assert!(matches!(from_hex("zz"), Err(PsbtError::Hex(_))));
+        assert!(matches!(from_hex("0011"), Err(PsbtError::InvalidMagic)));
+    }
+
+    #[test]
+    fn v2_round_trip_keeps_fields() {
+        let psbt = from_base64(SIGNED).unwrap();
+        let imported = ImportedPsbt::from_bytes(&to_bytes_as(&psbt, PsbtVersion::V2).unwrap()).unwrap();
+        assert_eq!(imported.version, PsbtVersion::V2);
+        assert_eq!(to_base64(&imported.psbt), SIGNED);
+    }
+
+    #[test]
+    fn fee_and_rate() {
+        let psbt = from_base64(UNSIGNED).unwrap();
+        assert_eq!(fee(&psbt).unwrap(), 1000);
+        // 452 weight units of unsigned transaction, 113 vbytes
+        assert!((fee_rate(&psbt).unwrap() - 1000.0 / 113.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn fee_needs_utxos() {
+        let mut psbt = from_base64(UNSIGNED).unwrap();
+        psbt.inputs[0].witness_utxo = None;
+        assert!(matches!(fee(&psbt), Err(PsbtError::MissingUtxo(0))));
+    }
+
+    #[test]
+    fn fee_rejects_overspending() {
+        let mut psbt = from_base64(UNSIGNED).unwrap();
+        psbt.global.unsigned_tx.output[0].value = 100_000;
+        assert!(matches!(fee(&psbt), Err(PsbtError::NegativeFee)));
+    }
+
+    #[test]
+    fn fee_rejects_overflowing_amounts() {
+        let mut psbt = from_base64(UNSIGNED).unwrap();
+        psbt.global.unsigned_tx.output[0].value = u64::MAX;
+        assert!(matches!(fee(&psbt), Err(PsbtError::AmountOverflow)));
+
+        let mut psbt = from_base64(UNSIGNED).unwrap();
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().value = u64::MAX;
+        let txin = psbt.global.unsigned_tx.input[0].clone();
+        psbt.global.unsigned_tx.input.push(txin);
+        let input = psbt.inputs[0].clone();
+        psbt.inputs.push(input);
+        assert!(matches!(fee(&psbt), Err(PsbtError::AmountOverflow)));
+        assert_eq!(summary(&psbt, Network::Regtest).fee, None);
+    }
+
+    #[test]
+    fn combine_merges_signatures() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let signed = from_base64(SIGNED).unwrap();
+        let combined = combine(vec![unsigned.clone(), signed.clone()]).unwrap();
+        assert_eq!(to_base64(&combined), SIGNED);
+        let combined = combine(vec![signed, unsigned]).unwrap();
+        assert_eq!(to_base64(&combined), SIGNED);
+    }
+
+    fn fingerprint() -> Fingerprint {
+        Fingerprint::from(&[0xd3, 0x4d, 0xb3, 0x3f][..])
+    }
+
+    fn input_key() -> PublicKey {
+        PublicKey::from_str("031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f").unwrap()
+    }
+
+    /// A valid signature of `UNSIGNED`'s input, but with SIGHASH_NONE.
+    const SIGHASH_NONE_SIG: &str = "3045022100a9ba94d02da79cf4715a6638fb36c6a5446ff5296c63e448612be525e2dea2ec02203e78e82eb370d87f08ce543ab402ca3ef944dab81b672dcad6492d8afe10dbf902";
+
+    #[test]
+    fn verify_accepts_good_signature() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let signed = from_base64(SIGNED).unwrap();
+        verify_signed(&unsigned, &signed, fingerprint()).unwrap();
+    }
+
+    #[test]
+    fn verify_requires_signature() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let result = verify_signed(&unsigned, &unsigned, fingerprint());
+        assert!(matches!(result, Err(PsbtError::MissingSignature(0))));
+    }
+
+    #[test]
+    fn verify_rejects_tampered_outputs() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let mut signed = from_base64(SIGNED).unwrap();
+        signed.global.unsigned_tx.output[0].value = 99_000;
+        let result = verify_signed(&unsigned, &signed, fingerprint());
+        assert!(matches!(result, Err(PsbtError::TransactionChanged)));
+
+        let mut signed = from_base64(SIGNED).unwrap();
+        let origin = unsigned.inputs[0].hd_keypaths[&input_key()].clone();
+        signed.outputs[1].hd_keypaths.insert(input_key(), origin);
+        let result = verify_signed(&unsigned, &signed, fingerprint());
+        assert!(matches!(result, Err(PsbtError::OutputChanged(1))));
+    }
+
+    #[test]
+    fn verify_rejects_swapped_utxo() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let mut signed = from_base64(SIGNED).unwrap();
+        if let Some(utxo) = signed.inputs[0].witness_utxo.as_mut() {
+            utxo.value = 1_000_000;
+        }
+        let result = verify_signed(&unsigned, &signed, fingerprint());
+        assert!(matches!(result, Err(PsbtError::InputChanged(0))));
+
+        let mut signed = from_base64(SIGNED).unwrap();
+        signed.inputs[0].witness_script = Some(Script::new());
+        let result = verify_signed(&unsigned, &signed, fingerprint());
+        assert!(matches!(result, Err(PsbtError::InputChanged(0))));
+    }
+
+    #[test]
+    fn verify_rejects_other_sighash_types() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let mut signed = from_base64(SIGNED).unwrap();
+        signed.inputs[0]
+            .partial_sigs
+            .insert(input_key(), Vec::from_hex(SIGHASH_NONE_SIG).unwrap());
+        let result = verify_signed(&unsigned, &signed, fingerprint());
+        assert!(matches!(result, Err(PsbtError::WrongSigHash(0))));
+
+        // Nor can the signer ask for them in its copy
+        signed.inputs[0].sighash_type = Some(SigHashType::None);
+        let result = verify_signed(&unsigned, &signed, fingerprint());
+        assert!(matches!(result, Err(PsbtError::InputChanged(0))));
+    }
+
+    #[test]
+    fn verify_rejects_bad_signature() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let mut signed = from_base64(SIGNED).unwrap();
+        let sig = signed.inputs[0].partial_sigs.get_mut(&input_key()).unwrap();
+        // Flip a bit of s, keeping the DER encoding valid
+        let last = sig.len() - 2;
+        sig[last] ^= 1;
+        let result = verify_signed(&unsigned, &signed, fingerprint());
+        assert!(matches!(result, Err(PsbtError::InvalidSignature(0))));
+    }
+
+    fn finalized(sig: Vec<u8>) -> PartiallySignedTransaction {
+        let mut psbt = from_base64(UNSIGNED).unwrap();
+        psbt.inputs[0].hd_keypaths.clear();
+        psbt.inputs[0].final_script_witness = Some(vec![sig, input_key().to_bytes()]);
+        psbt
+    }
+
+    #[test]
+    fn verify_checks_finalized_inputs() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let sig = from_base64(SIGNED).unwrap().inputs[0].partial_sigs[&input_key()].clone();
+        verify_signed(&unsigned, &finalized(sig), fingerprint()).unwrap();
+
+        let none_sig = Vec::from_hex(SIGHASH_NONE_SIG).unwrap();
+        let result = verify_signed(&unsigned, &finalized(none_sig), fingerprint());
+        assert!(matches!(result, Err(PsbtError::MissingSignature(0))));
+    }
+
+    #[test]
+    fn verify_cosigned_uses_the_wallet_signers() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let signed = from_base64(SIGNED).unwrap();
+        let other = Fingerprint::from(&[0xde, 0xad, 0xbe, 0xef][..]);
+        let signers = verify_cosigned(&unsigned, &signed, &[other, fingerprint()]).unwrap();
+        assert_eq!(signers, vec![fingerprint()]);
+        let result = verify_cosigned(&unsigned, &signed, &[other]);
+        assert!(matches!(result, Err(PsbtError::NotSigned)));
+        let result = verify_cosigned(&unsigned, &unsigned, &[fingerprint()]);
+        assert!(matches!(result, Err(PsbtError::NotSigned)));
+
+        let sig = signed.inputs[0].partial_sigs[&input_key()].clone();
+        let signers = verify_cosigned(&unsigned, &finalized(sig), &[fingerprint()]).unwrap();
+        assert_eq!(signers, vec![fingerprint()]);
+    }
+
+    #[test]
+    fn verify_cosigned_rejects_bad_signatures() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let mut signed = from_base64(SIGNED).unwrap();
+        // Claiming a key of ours came from a signer the wallet doesn't have changes nothing
+        signed.inputs[0].hd_keypaths.clear();
+        let signers = verify_cosigned(&unsigned, &signed, &[fingerprint()]).unwrap();
+        assert_eq!(signers, vec![fingerprint()]);
+
+        // Checked even when no wallet signer finished
+        let sig = signed.inputs[0].partial_sigs.get_mut(&input_key()).unwrap();
+        let last = sig.len() - 2;
+        sig[last] ^= 1;
+        let other = Fingerprint::from(&[0xde, 0xad, 0xbe, 0xef][..]);
+        let result = verify_cosigned(&unsigned, &signed, &[other]);
+        assert!(matches!(result, Err(PsbtError::InvalidSignature(0))));
+    }
+
+    #[test]
+    fn combine_rejects_other_transactions() {
+        let unsigned = from_base64(UNSIGNED).unwrap();
+        let mut other = unsigned.clone();
+        other.global.unsigned_tx.lock_time = 1;
+        assert!(matches!(combine(vec![unsigned, other]), Err(PsbtError::Psbt(_))));
+        assert!(matches!(combine(vec![]), Err(PsbtError::NothingToCombine)));
+    }
+}
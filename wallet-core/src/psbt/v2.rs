@@ -0,0 +1,493 @@
+//! Conversion between PSBTv0 and PSBTv2 (BIP370).
+//!
+//! rust-bitcoin only understands v0, so both directions work on the raw key-value maps and
+//! leave every field they don't know about untouched.
+
+use std::io::Cursor;
+
+use bdk::bitcoin::{
+    consensus::{deserialize, encode::VarInt, serialize, Decodable},
+    util::psbt::PartiallySignedTransaction,
+    OutPoint, Script, Transaction, TxIn, TxOut, Txid,
+};
+
+use super::PsbtError;
+
+const MAGIC: &[u8] = b"psbt\xff";
+
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const GLOBAL_TX_VERSION: u8 = 0x02;
+const GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const GLOBAL_INPUT_COUNT: u8 = 0x04;
+const GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const GLOBAL_VERSION: u8 = 0xfb;
+
+const IN_PREVIOUS_TXID: u8 = 0x0e;
+const IN_OUTPUT_INDEX: u8 = 0x0f;
+const IN_SEQUENCE: u8 = 0x10;
+const IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+
+const OUT_AMOUNT: u8 = 0x03;
+const OUT_SCRIPT: u8 = 0x04;
+
+/// Locktimes below this are block heights, the rest are timestamps.
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+const V2_GLOBALS: &[(u8, &str)] = &[
+    (GLOBAL_TX_VERSION, "transaction version"),
+    (GLOBAL_FALLBACK_LOCKTIME, "fallback locktime"),
+    (GLOBAL_INPUT_COUNT, "input count"),
+    (GLOBAL_OUTPUT_COUNT, "output count"),
+    (GLOBAL_TX_MODIFIABLE, "transaction modifiable flags"),
+];
+const V2_INPUTS: &[(u8, &str)] = &[
+    (IN_PREVIOUS_TXID, "previous txid"),
+    (IN_OUTPUT_INDEX, "output index"),
+    (IN_SEQUENCE, "sequence"),
+    (IN_REQUIRED_TIME_LOCKTIME, "required time locktime"),
+    (IN_REQUIRED_HEIGHT_LOCKTIME, "required height locktime"),
+];
+const V2_OUTPUTS: &[(u8, &str)] = &[(OUT_AMOUNT, "output amount"), (OUT_SCRIPT, "output script")];
+
+type Map = Vec<(Vec<u8>, Vec<u8>)>;
+
+struct RawPsbt {
+    global: Map,
+    inputs: Vec<Map>,
+    outputs: Vec<Map>,
+}
+
+/// All the fields we read or rewrite have no key data, so the key is just the type byte.
+fn is_key(key: &[u8], key_type: u8) -> bool {
+    key.len() == 1 && key[0] == key_type
+}
+
+fn get(map: &Map, key_type: u8) -> Option<&[u8]> {
+    map.iter()
+        .find(|(key, _)| is_key(key, key_type))
+        .map(|(_, value)| &value[..])
+}
+
+fn require<'a>(map: &'a Map, key_type: u8, name: &'static str) -> Result<&'a [u8], PsbtError> {
+    get(map, key_type).ok_or(PsbtError::MissingV2Field(name))
+}
+
+fn remove(map: &mut Map, key_types: &[u8]) {
+    map.retain(|(key, _)| !key_types.iter().any(|key_type| is_key(key, *key_type)));
+}
+
+fn read_map(cursor: &mut Cursor<&[u8]>) -> Result<Map, PsbtError> {
+    let mut map = vec![];
+    loop {
+        let key_len = VarInt::consensus_decode(&mut *cursor)?.0 as usize;
+        if key_len == 0 {
+            return Ok(map);
+        }
+        let key = read_bytes(cursor, key_len)?;
+        let value_len = VarInt::consensus_decode(&mut *cursor)?.0 as usize;
+        let value = read_bytes(cursor, value_len)?;
+        map.push((key, value));
+    }
+}
+
+fn read_bytes(cursor: &mut Cursor<&[u8]>, len: usize) -> Result<Vec<u8>, PsbtError> {
+    let start = cursor.position() as usize;
+    let bytes = cursor.get_ref();
+    // `len` comes straight from the PSBT, so don't trust it not to overflow
+    let end = start.checked_add(len).ok_or(PsbtError::Truncated)?;
+    if end > bytes.len() {
+        return Err(PsbtError::Truncated);
+    }
+    cursor.set_position(end as u64);
+    Ok(bytes[start..end].to_vec())
+}
+
+fn write_map(out: &mut Vec<u8>, map: &Map) {
+    for (key, value) in map {
+        out.extend(serialize(&VarInt(key.len() as u64)));
+        out.extend_from_slice(key);
+        out.extend(serialize(&VarInt(value.len() as u64)));
+        out.extend_from_slice(value);
+    }
+    out.push(0x00);
+}
+
+fn read_u32(value: &[u8]) -> Result<u32, PsbtError> {
+    Ok(deserialize(value)?)
+}
+
+fn read_count(value: &[u8]) -> Result<usize, PsbtError> {
+    Ok(deserialize::<VarInt>(value)?.0 as usize)
+}
+
+impl RawPsbt {
+    fn parse(bytes: &[u8]) -> Result<Self, PsbtError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(PsbtError::InvalidMagic);
+        }
+        let mut cursor = Cursor::new(bytes);
+        cursor.set_position(MAGIC.len() as u64);
+
+        let global = read_map(&mut cursor)?;
+        let (input_count, output_count) = match get(&global, GLOBAL_UNSIGNED_TX) {
+            Some(tx) => {
+                let tx: Transaction = deserialize(tx)?;
+                (tx.input.len(), tx.output.len())
+            }
+            None => (
+                read_count(require(&global, GLOBAL_INPUT_COUNT, "input count")?)?,
+                read_count(require(&global, GLOBAL_OUTPUT_COUNT, "output count")?)?,
+            ),
+        };
+
+        let mut inputs = vec![];
+        for _ in 0..input_count {
+            inputs.push(read_map(&mut cursor)?);
+        }
+        let mut outputs = vec![];
+        for _ in 0..output_count {
+            outputs.push(read_map(&mut cursor)?);
+        }
+
+        Ok(Self {
+            global,
+            inputs,
+            outputs,
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        write_map(&mut out, &self.global);
+        for input in &self.inputs {
+            write_map(&mut out, input);
+        }
+        for output in &self.outputs {
+            write_map(&mut out, output);
+        }
+        out
+    }
+
+    /// BIP370 makes a v0 PSBT with any of the v2 only fields invalid.
+    fn check_no_v2_fields(&self) -> Result<(), PsbtError> {
+        let maps = std::iter::once((&self.global, V2_GLOBALS))
+            .chain(self.inputs.iter().map(|map| (map, V2_INPUTS)))
+            .chain(self.outputs.iter().map(|map| (map, V2_OUTPUTS)));
+        for (map, fields) in maps {
+            if let Some((_, name)) = fields.iter().find(|(key_type, _)| get(map, *key_type).is_some()) {
+                return Err(PsbtError::UnexpectedField(name));
+            }
+        }
+        Ok(())
+    }
+
+    fn version(&self) -> Result<u32, PsbtError> {
+        match get(&self.global, GLOBAL_VERSION) {
+            Some(version) => read_u32(version),
+            None => Ok(0),
+        }
+    }
+}
+
+/// Returns the PSBT version declared in the global map, `0` if absent.
+pub fn version(bytes: &[u8]) -> Result<u32, PsbtError> {
+    RawPsbt::parse(bytes)?.version()
+}
+
+/// Picks the transaction locktime following the rules in BIP370.
+fn determine_locktime(raw: &RawPsbt) -> Result<u32, PsbtError> {
+    let mut heights = vec![];
+    let mut times = vec![];
+    let mut height_supported = true;
+    let mut time_supported = true;
+
+    for input in &raw.inputs {
+        let height = get(input, IN_REQUIRED_HEIGHT_LOCKTIME).map(read_u32).transpose()?;
+        let time = get(input, IN_REQUIRED_TIME_LOCKTIME).map(read_u32).transpose()?;
+        if matches!(height, Some(height) if height >= LOCKTIME_THRESHOLD)
+            || matches!(time, Some(time) if time < LOCKTIME_THRESHOLD)
+        {
+            return Err(PsbtError::InvalidLocktime);
+        }
+        if height.is_none() && time.is_none() {
+            continue;
+        }
+        match height {
+            Some(height) => heights.push(height),
+            None => height_supported = false,
+        }
+        match time {
+            Some(time) => times.push(time),
+            None => time_supported = false,
+        }
+    }
+
+    if heights.is_empty() && times.is_empty() {
+        return match get(&raw.global, GLOBAL_FALLBACK_LOCKTIME) {
+            Some(locktime) => read_u32(locktime),
+            None => Ok(0),
+        };
+    }
+
+    if height_supported {
+        Ok(heights.into_iter().max().unwrap_or(0))
+    } else if time_supported {
+        Ok(times.into_iter().max().unwrap_or(0))
+    } else {
+        Err(PsbtError::ConflictingLocktimes)
+    }
+}
+
+/// Rewrites a PSBTv2 into the v0 encoding rust-bitcoin can parse.
+pub fn to_v0(bytes: &[u8]) -> Result<PartiallySignedTransaction, PsbtError> {
+    let mut raw = RawPsbt::parse(bytes)?;
+    match raw.version()? {
+        0 => {
+            raw.check_no_v2_fields()?;
+            return Ok(deserialize(bytes)?);
+        }
+        2 => (),
+        version => return Err(PsbtError::UnsupportedVersion(version)),
+    }
+    if get(&raw.global, GLOBAL_UNSIGNED_TX).is_some() {
+        return Err(PsbtError::UnexpectedField("unsigned transaction"));
+    }
+
+    let mut input = vec![];
+    for map in &raw.inputs {
+        let txid: Txid = deserialize(require(map, IN_PREVIOUS_TXID, "previous txid")?)?;
+        let vout = read_u32(require(map, IN_OUTPUT_INDEX, "output index")?)?;
+        let sequence = match get(map, IN_SEQUENCE) {
+            Some(sequence) => read_u32(sequence)?,
+            None => 0xffff_ffff,
+        };
+        input.push(TxIn {
+            previous_output: OutPoint { txid, vout },
+            script_sig: Script::new(),
+            sequence,
+            witness: vec![],
+        });
+    }
+
+    let mut output = vec![];
+    for (index, map) in raw.outputs.iter().enumerate() {
+        let value: i64 = deserialize(require(map, OUT_AMOUNT, "output amount")?)?;
+        if value < 0 {
+            return Err(PsbtError::NegativeAmount(index));
+        }
+        let script = require(map, OUT_SCRIPT, "output script")?;
+        output.push(TxOut {
+            value: value as u64,
+            script_pubkey: Script::from(script.to_vec()),
+        });
+    }
+
+    let tx = Transaction {
+        version: deserialize(require(&raw.global, GLOBAL_TX_VERSION, "transaction version")?)?,
+        lock_time: determine_locktime(&raw)?,
+        input,
+        output,
+    };
+
+    remove(
+        &mut raw.global,
+        &[
+            GLOBAL_TX_VERSION,
+            GLOBAL_FALLBACK_LOCKTIME,
+            GLOBAL_INPUT_COUNT,
+            GLOBAL_OUTPUT_COUNT,
+            GLOBAL_TX_MODIFIABLE,
+            GLOBAL_VERSION,
+        ],
+    );
+    raw.global.insert(0, (vec![GLOBAL_UNSIGNED_TX], serialize(&tx)));
+    for map in raw.inputs.iter_mut() {
+        remove(
+            map,
+            &[
+                IN_PREVIOUS_TXID,
+                IN_OUTPUT_INDEX,
+                IN_SEQUENCE,
+                IN_REQUIRED_TIME_LOCKTIME,
+                IN_REQUIRED_HEIGHT_LOCKTIME,
+            ],
+        );
+    }
+    for map in raw.outputs.iter_mut() {
+        remove(map, &[OUT_AMOUNT, OUT_SCRIPT]);
+    }
+
+    Ok(deserialize(&raw.serialize())?)
+}
+
+/// Rewrites a v0 PSBT into the PSBTv2 encoding.
+pub fn from_v0(psbt: &PartiallySignedTransaction) -> Result<Vec<u8>, PsbtError> {
+    let tx = &psbt.global.unsigned_tx;
+    let mut raw = RawPsbt::parse(&serialize(psbt))?;
+
+    remove(&mut raw.global, &[GLOBAL_UNSIGNED_TX, GLOBAL_VERSION]);
+    raw.global.push((vec![GLOBAL_TX_VERSION], serialize(&tx.version)));
+    raw.global.push((vec![GLOBAL_FALLBACK_LOCKTIME], serialize(&tx.lock_time)));
+    raw.global.push((vec![GLOBAL_INPUT_COUNT], serialize(&VarInt(tx.input.len() as u64))));
+    raw.global.push((vec![GLOBAL_OUTPUT_COUNT], serialize(&VarInt(tx.output.len() as u64))));
+    raw.global.push((vec![GLOBAL_VERSION], serialize(&2u32)));
+
+    for (map, txin) in raw.inputs.iter_mut().zip(tx.input.iter()) {
+        map.push((vec![IN_PREVIOUS_TXID], serialize(&txin.previous_output.txid)));
+        map.push((vec![IN_OUTPUT_INDEX], serialize(&txin.previous_output.vout)));
+        map.push((vec![IN_SEQUENCE], serialize(&txin.sequence)));
+    }
+    for (map, txout) in raw.outputs.iter_mut().zip(tx.output.iter()) {
+        map.push((vec![OUT_AMOUNT], serialize(&(txout.value as i64))));
+        map.push((vec![OUT_SCRIPT], txout.script_pubkey.to_bytes()));
+    }
+
+    Ok(raw.serialize())
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk::bitcoin::hashes::hex::FromHex;
+
+    use super::*;
+
+    fn entry(key_type: u8, value: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+        (vec![key_type], value)
+    }
+
+    /// The smallest valid PSBTv2 in BIP370: one input, one output, no optional fields.
+    fn minimal() -> RawPsbt {
+        RawPsbt {
+            global: vec![
+                entry(GLOBAL_TX_VERSION, serialize(&2i32)),
+                entry(GLOBAL_INPUT_COUNT, serialize(&VarInt(1))),
+                entry(GLOBAL_OUTPUT_COUNT, serialize(&VarInt(1))),
+                entry(GLOBAL_VERSION, serialize(&2u32)),
+            ],
+            inputs: vec![vec![
+                entry(IN_PREVIOUS_TXID, vec![0x11; 32]),
+                entry(IN_OUTPUT_INDEX, serialize(&1u32)),
+            ]],
+            outputs: vec![vec![
+                entry(OUT_AMOUNT, serialize(&60_000i64)),
+                entry(OUT_SCRIPT, Vec::from_hex("001479b000887626b294a914501a4cd226b58b235983").unwrap()),
+            ]],
+        }
+    }
+
+    fn without(mut raw: RawPsbt, key_type: u8) -> RawPsbt {
+        remove(&mut raw.global, &[key_type]);
+        raw.inputs.iter_mut().for_each(|map| remove(map, &[key_type]));
+        raw.outputs.iter_mut().for_each(|map| remove(map, &[key_type]));
+        raw
+    }
+
+    #[test]
+    fn converts_minimal_v2() {
+        let psbt = to_v0(&minimal().serialize()).unwrap();
+        let tx = &psbt.global.unsigned_tx;
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.lock_time, 0);
+        assert_eq!(tx.input[0].previous_output.vout, 1);
+        assert_eq!(tx.input[0].sequence, 0xffff_ffff);
+        assert_eq!(tx.output[0].value, 60_000);
+        assert_eq!(version(&minimal().serialize()).unwrap(), 2);
+    }
+
+    #[test]
+    fn v2_round_trip() {
+        let psbt = to_v0(&minimal().serialize()).unwrap();
+        let v2 = from_v0(&psbt).unwrap();
+        assert_eq!(version(&v2).unwrap(), 2);
+        assert_eq!(to_v0(&v2).unwrap(), psbt);
+    }
+
+    #[test]
+    fn v0_round_trip() {
+        let psbt = to_v0(&minimal().serialize()).unwrap();
+        let v0 = serialize(&psbt);
+        assert_eq!(version(&v0).unwrap(), 0);
+        assert_eq!(serialize(&to_v0(&from_v0(&psbt).unwrap()).unwrap()), v0);
+    }
+
+    #[test]
+    fn uses_fallback_locktime() {
+        let mut raw = minimal();
+        raw.global.push(entry(GLOBAL_FALLBACK_LOCKTIME, serialize(&700_000u32)));
+        assert_eq!(to_v0(&raw.serialize()).unwrap().global.unsigned_tx.lock_time, 700_000);
+    }
+
+    #[test]
+    fn prefers_height_locktimes() {
+        let mut raw = minimal();
+        raw.inputs[0].push(entry(IN_REQUIRED_HEIGHT_LOCKTIME, serialize(&10_000u32)));
+        raw.inputs[0].push(entry(IN_REQUIRED_TIME_LOCKTIME, serialize(&1_657_048_460u32)));
+        assert_eq!(to_v0(&raw.serialize()).unwrap().global.unsigned_tx.lock_time, 10_000);
+    }
+
+    #[test]
+    fn rejects_missing_required_fields() {
+        for key_type in &[GLOBAL_TX_VERSION, IN_PREVIOUS_TXID, IN_OUTPUT_INDEX, OUT_AMOUNT, OUT_SCRIPT] {
+            let bytes = without(minimal(), *key_type).serialize();
+            assert!(matches!(to_v0(&bytes), Err(PsbtError::MissingV2Field(_))));
+        }
+        let bytes = without(minimal(), GLOBAL_INPUT_COUNT).serialize();
+        assert!(matches!(to_v0(&bytes), Err(PsbtError::MissingV2Field(_))));
+    }
+
+    #[test]
+    fn rejects_unsigned_tx_in_v2() {
+        let psbt = to_v0(&minimal().serialize()).unwrap();
+        let mut raw = minimal();
+        raw.global.push(entry(GLOBAL_UNSIGNED_TX, serialize(&psbt.global.unsigned_tx)));
+        assert!(matches!(to_v0(&raw.serialize()), Err(PsbtError::UnexpectedField(_))));
+    }
+
+    #[test]
+    fn rejects_v2_fields_in_v0() {
+        let psbt = to_v0(&minimal().serialize()).unwrap();
+        for (key_type, in_input) in &[(IN_PREVIOUS_TXID, true), (OUT_AMOUNT, false)] {
+            let mut raw = RawPsbt::parse(&serialize(&psbt)).unwrap();
+            let map = if *in_input { &mut raw.inputs[0] } else { &mut raw.outputs[0] };
+            map.push(entry(*key_type, serialize(&0u32)));
+            assert!(matches!(to_v0(&raw.serialize()), Err(PsbtError::UnexpectedField(_))));
+        }
+        let mut raw = RawPsbt::parse(&serialize(&psbt)).unwrap();
+        raw.global.push(entry(GLOBAL_TX_VERSION, serialize(&2i32)));
+        assert!(matches!(to_v0(&raw.serialize()), Err(PsbtError::UnexpectedField(_))));
+    }
+
+    #[test]
+    fn rejects_out_of_range_locktimes() {
+        let mut raw = minimal();
+        raw.inputs[0].push(entry(IN_REQUIRED_TIME_LOCKTIME, serialize(&499_999_999u32)));
+        assert!(matches!(to_v0(&raw.serialize()), Err(PsbtError::InvalidLocktime)));
+
+        let mut raw = minimal();
+        raw.inputs[0].push(entry(IN_REQUIRED_HEIGHT_LOCKTIME, serialize(&500_000_000u32)));
+        assert!(matches!(to_v0(&raw.serialize()), Err(PsbtError::InvalidLocktime)));
+    }
+
+    #[test]
+    fn rejects_negative_amounts() {
+        let mut raw = minimal();
+        remove(&mut raw.outputs[0], &[OUT_AMOUNT]);
+        raw.outputs[0].push(entry(OUT_AMOUNT, serialize(&-1i64)));
+        assert!(matches!(to_v0(&raw.serialize()), Err(PsbtError::NegativeAmount(0))));
+    }
+
+    #[test]
+    fn rejects_oversized_lengths() {
+        let mut bytes = MAGIC.to_vec();
+        // A key length of u64::MAX
+        bytes.push(0xff);
+        bytes.extend(&[0xff; 8]);
+        assert!(matches!(version(&bytes), Err(PsbtError::Truncated)));
+
+        let mut truncated = minimal().serialize();
+        truncated.truncate(truncated.len() - 10);
+        assert!(matches!(version(&truncated), Err(PsbtError::Truncated)));
+    }
+}
@@ -0,0 +1,406 @@
+//! Animated QR codes for exchanging PSBTs with air-gapped signers, in the UR `crypto-psbt`
+//! (BCR-2020-006) and BBQr formats.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+};
+
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+
+use crate::psbt::{self, PsbtError};
+
+const UR_TYPE: &str = "crypto-psbt";
+/// Bytes of PSBT per UR frame, small enough for phone cameras to read
+const UR_FRAGMENT_LEN: usize = 200;
+/// Base32 characters of PSBT per BBQr frame, a multiple of 8 so every frame decodes alone
+const BBQR_FRAGMENT_LEN: usize = 320;
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// Largest PSBT we inflate from zlib compressed BBQr frames
+const BBQR_MAX_INFLATED: usize = 4_000_000;
+
+#[derive(Debug)]
+pub enum QrPsbtError {
+    Ur(String),
+    Bbqr(&'static str),
+    /// The QR codes hold something other than a PSBT
+    WrongType(String),
+    Psbt(PsbtError),
+    Image(String),
+    NoQrCode,
+}
+
+impl fmt::Display for QrPsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QrPsbtError::Ur(err) => write!(f, "Invalid UR: {}", err),
+            QrPsbtError::Bbqr(err) => write!(f, "Invalid BBQr: {}", err),
+            QrPsbtError::WrongType(found) => write!(f, "Expected a PSBT QR code, found {}", found),
+            QrPsbtError::Psbt(err) => write!(f, "{}", err),
+            QrPsbtError::Image(err) => write!(f, "Couldn't read the image: {}", err),
+            QrPsbtError::NoQrCode => write!(f, "No QR code found in the image"),
+        }
+    }
+}
+
+impl std::error::Error for QrPsbtError {}
+
+impl From<PsbtError> for QrPsbtError {
+    fn from(err: PsbtError) -> Self {
+        QrPsbtError::Psbt(err)
+    }
+}
+
+impl From<ur::Error> for QrPsbtError {
+    fn from(err: ur::Error) -> Self {
+        QrPsbtError::Ur(format!("{:?}", err))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimatedFormat {
+    /// Keystone, Passport, Jade and SeedSigner
+    Ur,
+    /// Coldcard Q and newer SeedSigner firmware
+    Bbqr,
+}
+
+/// The frames to cycle through to show `psbt`, uppercase so they fit the denser alphanumeric
+/// QR mode.
+pub fn encode_psbt(
+    psbt: &PartiallySignedTransaction,
+    format: AnimatedFormat,
+) -> Result<Vec<String>, QrPsbtError> {
+    let bytes = psbt::to_bytes(psbt);
+    match format {
+        AnimatedFormat::Ur => {
+            let mut encoder = ur::Encoder::new(&cbor_bytes(&bytes), UR_FRAGMENT_LEN, UR_TYPE)?;
+            (0..encoder.fragment_count())
+                .map(|_| Ok(encoder.next_part()?.to_uppercase()))
+                .collect()
+        }
+        AnimatedFormat::Bbqr => {
+            let encoded = base32_encode(&bytes);
+            let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(BBQR_FRAGMENT_LEN).collect();
+            if chunks.len() > 36 * 36 {
+                return Err(QrPsbtError::Bbqr("PSBT is too large"));
+            }
+            Ok(chunks
+                .iter()
+                .enumerate()
+                .map(|(index, chunk)| {
+                    format!(
+                        "B$2P{}{}{}",
+                        base36(chunks.len()),
+                        base36(index),
+                        String::from_utf8_lossy(chunk)
+                    )
+                })
+                .collect())
+        }
+    }
+}
+
+/// How far along decoding the scanned frames is.
+#[derive(Debug, Clone)]
+pub enum Progress {
+    Complete(PartiallySignedTransaction),
+    Partial { received: usize, total: usize },
+}
+
+/// Decodes a PSBT from scanned UR or BBQr frames, in any order and with repeats.
+pub fn decode_frames(frames: &[String]) -> Result<Progress, QrPsbtError> {
+    let first = frames.first().ok_or(QrPsbtError::NoQrCode)?;
+    if first.starts_with("B$") {
+        decode_bbqr(frames)
+    } else {
+        decode_ur(frames)
+    }
+}
+
+fn decode_ur(frames: &[String]) -> Result<Progress, QrPsbtError> {
+    let mut decoder = ur::Decoder::default();
+    let mut received = BTreeSet::new();
+    let mut total = 1;
+    for frame in frames {
+        let frame = frame.trim().to_lowercase();
+        let mut parts = frame.splitn(3, '/');
+        let ur_type = parts.next().unwrap_or("").trim_start_matches("ur:");
+        if ur_type != UR_TYPE {
+            return Err(QrPsbtError::WrongType(ur_type.to_string()));
+        }
+        match (parts.next(), parts.next()) {
+            // A single part UR, no fountain codes needed
+            (Some(_), None) => {
+                let (_, message) = ur::decode(&frame)?;
+                return complete(&message);
+            }
+            (Some(sequence), Some(_)) => {
+                let mut numbers = sequence.splitn(2, '-').map(|n| n.parse::<usize>());
+                if let (Some(Ok(index)), Some(Ok(count))) = (numbers.next(), numbers.next()) {
+                    received.insert(index);
+                    total = count;
+                }
+                decoder.receive(&frame)?;
+            }
+            _ => return Err(QrPsbtError::Ur("missing payload".into())),
+        }
+        if decoder.complete() {
+            let message = decoder.message()?.ok_or_else(|| QrPsbtError::Ur("incomplete".into()))?;
+            return complete(&message);
+        }
+    }
+    Ok(Progress::Partial {
+        received: received.iter().filter(|index| **index <= total).count(),
+        total,
+    })
+}
+
+fn complete(cbor: &[u8]) -> Result<Progress, QrPsbtError> {
+    let bytes = cbor_bytes_decode(cbor).ok_or_else(|| QrPsbtError::Ur("expected CBOR bytes".into()))?;
+    Ok(Progress::Complete(psbt::from_bytes(bytes)?))
+}
+
+fn decode_bbqr(frames: &[String]) -> Result<Progress, QrPsbtError> {
+    let mut parts = BTreeMap::new();
+    let mut total = 0;
+    let mut compressed = None;
+    for frame in frames {
+        let frame = frame.trim();
+        let header = frame
+            .get(..8)
+            .filter(|header| header.is_ascii())
+            .ok_or(QrPsbtError::Bbqr("invalid header"))?;
+        let (encoding, file_type) = (&header[2..3], &header[3..4]);
+        if file_type != "P" {
+            return Err(QrPsbtError::WrongType(format!("BBQr file type {}", file_type)));
+        }
+        let frame_total = from_base36(&header[4..6]).ok_or(QrPsbtError::Bbqr("invalid part count"))?;
+        let index = from_base36(&header[6..8]).ok_or(QrPsbtError::Bbqr("invalid part index"))?;
+        let frame_compressed = encoding == "Z";
+        if (total != 0 && frame_total != total)
+            || index >= frame_total
+            || matches!(compressed, Some(compressed) if compressed != frame_compressed)
+        {
+            return Err(QrPsbtError::Bbqr("frames are from different files"));
+        }
+        total = frame_total;
+        compressed = Some(frame_compressed);
+
+        // Compressed frames are base32 too, the deflate stream is split across them
+        let data = match encoding {
+            "2" | "Z" => base32_decode(&frame[8..]).ok_or(QrPsbtError::Bbqr("invalid base32"))?,
+            "H" => hex_decode(&frame[8..]).ok_or(QrPsbtError::Bbqr("invalid hex"))?,
+            _ => return Err(QrPsbtError::Bbqr("unknown encoding")),
+        };
+        parts.insert(index, data);
+    }
+
+    if parts.len() < total {
+        return Ok(Progress::Partial {
+            received: parts.len(),
+            total,
+        });
+    }
+    let mut bytes: Vec<u8> = parts.into_iter().flat_map(|(_, data)| data).collect();
+    if compressed == Some(true) {
+        // Raw deflate without a zlib header, which any window size up to 32KB inflates
+        bytes = miniz_oxide::inflate::decompress_to_vec_with_limit(&bytes, BBQR_MAX_INFLATED)
+            .map_err(|_| QrPsbtError::Bbqr("invalid zlib data"))?;
+    }
+    Ok(Progress::Complete(psbt::from_bytes(&bytes)?))
+}
+
+/// Reads every QR code in an image file, e.g. a photo or screenshot of a signer's screen.
+pub fn read_qr_codes(path: &Path) -> Result<Vec<String>, QrPsbtError> {
+    let image = image::open(path)
+        .map_err(|err| QrPsbtError::Image(err.to_string()))?
+        .to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare(image);
+    let codes: Vec<String> = prepared
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, content)| content)
+        .collect();
+    if codes.is_empty() {
+        return Err(QrPsbtError::NoQrCode);
+    }
+    Ok(codes)
+}
+
+/// Wraps `bytes` in a CBOR byte string, the `crypto-psbt` encoding.
+fn cbor_bytes(bytes: &[u8]) -> Vec<u8> {
+    let len = bytes.len();
+    let mut out = match len {
+        0..=23 => vec![0x40 | len as u8],
+        24..=0xff => vec![0x58, len as u8],
+        0x100..=0xffff => {
+            let mut header = vec![0x59];
+            header.extend(&(len as u16).to_be_bytes());
+            header
+        }
+        _ => {
+            let mut header = vec![0x5a];
+            header.extend(&(len as u32).to_be_bytes());
+            header
+        }
+    };
+    out.extend(bytes);
+    out
+}
+
+fn cbor_bytes_decode(cbor: &[u8]) -> Option<&[u8]> {
+    let (len, header) = match *cbor.first()? {
+        byte @ 0x40..=0x57 => ((byte & 0x1f) as usize, 1),
+        0x58 => (*cbor.get(1)? as usize, 2),
+        0x59 => (u16::from_be_bytes([*cbor.get(1)?, *cbor.get(2)?]) as usize, 3),
+        0x5a => {
+            let mut len = [0u8; 4];
+            len.copy_from_slice(cbor.get(1..5)?);
+            (u32::from_be_bytes(len) as usize, 5)
+        }
+        _ => return None,
+    };
+    cbor.get(header..header + len)
+}
+
+fn base36(n: usize) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    format!("{}{}", DIGITS[n / 36] as char, DIGITS[n % 36] as char)
+}
+
+fn from_base36(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 36).ok()
+}
+
+/// RFC 4648 base32 without padding, as BBQr uses.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        out.push(BASE32[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in s.trim_end_matches('=').bytes() {
+        let value = BASE32.iter().position(|b| *b == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    Some(out)
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PSBT: &str = "cHNidP8BAHECAAAAARERERERERERERERERERERERERERERERERERERERERERAAAAAAD9////AmDqAAAAAAAAFgAU68DuCyq56Cd6YAwlFHXiKjJBocFYmAAAAAAAABYAFHmwAIh2JrKUqRRQGkzSJrWLI1mDAAAAAAABAR+ghgEAAAAAABYAFHmwAIh2JrKUqRRQGkzSJrWLI1mDIgYDG4TFVnsSZECZXT7VqroFZdceGDRgSBn/nBf16dXdB48Y002zP1QAAIABAACAAAAAgAAAAAAAAAAAAAAA";
+
+    fn complete_psbt(progress: Progress) -> PartiallySignedTransaction {
+        match progress {
+            Progress::Complete(psbt) => psbt,
+            Progress::Partial { received, total } => panic!("only {} of {} frames", received, total),
+        }
+    }
+
+    #[test]
+    fn base32_round_trip() {
+        // RFC 4648 test vectors, without the padding
+        assert_eq!(base32_encode(b""), "");
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("MZXW6YTBOI======").unwrap(), b"foobar");
+        assert_eq!(base32_decode("mzxw6ytboi").unwrap(), b"foobar");
+        assert!(base32_decode("MZXW1").is_none());
+
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(base32_decode(&base32_encode(&bytes)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn cbor_round_trip() {
+        for len in [0, 23, 24, 255, 256, 65535, 65536].iter() {
+            let bytes = vec![0xab; *len];
+            let cbor = cbor_bytes(&bytes);
+            assert_eq!(cbor_bytes_decode(&cbor), Some(&bytes[..]));
+        }
+        assert_eq!(cbor_bytes(&[1, 2, 3]), vec![0x43, 1, 2, 3]);
+        assert_eq!(cbor_bytes_decode(&[0x58, 2, 1]), None);
+        assert_eq!(cbor_bytes_decode(&[0x01]), None);
+    }
+
+    #[test]
+    fn ur_round_trip() {
+        let psbt = psbt::from_base64(PSBT).unwrap();
+        let frames = encode_psbt(&psbt, AnimatedFormat::Ur).unwrap();
+        assert!(frames[0].starts_with("UR:CRYPTO-PSBT/"));
+        assert_eq!(complete_psbt(decode_frames(&frames).unwrap()), psbt);
+    }
+
+    #[test]
+    fn bbqr_round_trip() {
+        let psbt = psbt::from_base64(PSBT).unwrap();
+        let frames = encode_psbt(&psbt, AnimatedFormat::Bbqr).unwrap();
+        assert!(frames.iter().all(|frame| frame.starts_with("B$2P")));
+
+        // Any order, and a partial set reports its progress
+        let mut reversed = frames.clone();
+        reversed.reverse();
+        assert_eq!(complete_psbt(decode_frames(&reversed).unwrap()), psbt);
+        if frames.len() > 1 {
+            let progress = decode_frames(&frames[..1]).unwrap();
+            assert!(matches!(progress, Progress::Partial { received: 1, .. }));
+        }
+    }
+
+    #[test]
+    fn bbqr_zlib_frames() {
+        // Compressed with Python's zlib at wbits -10, as BBQr specifies
+        let frames = vec![
+            "B$ZP0200FMUE4KXZZ7EFBSGEYDAMAKCIAAYIBQG777776MZFXQBLGGOEDBCF4H3YY6W3L44FPJKQFD5KJDUSGLJDY6CQOI3GYCSSWNZQOSKKS3M2WJJCIQGK".to_string(),
+            "B$ZP020146JNVVTO4XEGNMA4EOR7ZARWIZ6MVFGYTCSVXDUGKUF2KOGMRS23XOTKC5V6U5JZBGJQID6J75Z4JP56XR5JPPK74KZO6ZX3CADIMBVAIEGQYEAMAMAA".to_string(),
+        ];
+        let psbt = complete_psbt(decode_frames(&frames).unwrap());
+        assert_eq!(psbt::to_base64(&psbt), PSBT);
+
+        let mixed = vec![frames[0].clone(), frames[1].replacen("B$Z", "B$2", 1)];
+        assert!(matches!(decode_frames(&mixed), Err(QrPsbtError::Bbqr(_))));
+    }
+
+    #[test]
+    fn bbqr_rejects_other_file_types() {
+        let frames = vec!["B$2T0100MZXW6YTBOI".to_string()];
+        assert!(matches!(decode_frames(&frames), Err(QrPsbtError::WrongType(_))));
+        assert!(hex_decode("+1").is_none());
+    }
+}
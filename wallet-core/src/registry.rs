@@ -0,0 +1,224 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::address_book::Contact;
+use crate::labels::Label;
+use crate::storage::{Storage, StorageError};
+use crate::RegistrationStatus;
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Storage(StorageError),
+    Json(serde_json::Error),
+    DuplicateName(String),
+    NotFound(String),
+    ContactExists(String),
+    ContactNotFound(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Storage(err) => write!(f, "Couldn't access the wallet config: {}", err),
+            RegistryError::Json(err) => write!(f, "Wallet config is corrupted: {}", err),
+            RegistryError::DuplicateName(name) => write!(f, "A wallet named {} already exists", name),
+            RegistryError::NotFound(name) => write!(f, "No wallet named {}", name),
+            RegistryError::ContactExists(name) => write!(f, "A contact named {} already exists", name),
+            RegistryError::ContactNotFound(name) => write!(f, "No contact named {}", name),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<StorageError> for RegistryError {
+    fn from(err: StorageError) -> Self {
+        RegistryError::Storage(err)
+    }
+}
+
+impl From<serde_json::Error> for RegistryError {
+    fn from(err: serde_json::Error) -> Self {
+        RegistryError::Json(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultisigEntry {
+    pub threshold: usize,
+    /// Keys written `[fingerprint/path]xpub`
+    pub keys: Vec<String>,
+}
+
+/// Everything needed to recreate a `SlapsWallet` without its devices plugged in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalletEntry {
+    pub name: String,
+    pub descriptor: String,
+    pub change_descriptor: String,
+    pub network: String,
+    /// Electrum server URL
+    pub backend: String,
+    pub fingerprints: Vec<String>,
+    #[serde(default)]
+    pub multisig: Option<MultisigEntry>,
+    #[serde(default)]
+    pub registrations: BTreeMap<String, RegistrationStatus>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RegistryFile {
+    wallets: Vec<WalletEntry>,
+    #[serde(default)]
+    last_opened: Option<String>,
+    #[serde(default)]
+    contacts: Vec<Contact>,
+}
+
+/// Where the registry lives by default, e.g. `~/.local/share/slaps/wallets.json` on Linux.
+pub fn default_registry_path() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("slaps").join("wallets.json"))
+}
+
+/// The wallets we know about, kept as JSON in a password encrypted file.
+#[derive(Debug, Clone)]
+pub struct WalletRegistry {
+    storage: Storage,
+    wallets: Vec<WalletEntry>,
+    last_opened: Option<String>,
+    contacts: Vec<Contact>,
+}
+
+impl WalletRegistry {
+    /// Decrypts the registry at `path`, starting empty if the file doesn't exist yet.
+    pub fn unlock(path: &Path, password: &str) -> Result<Self, RegistryError> {
+        let (storage, contents) = Storage::open(path, password)?;
+        Self::from_contents(storage, contents.as_deref())
+    }
+
+    /// Encrypts a registry saved before encryption existed with `password` and unlocks it.
+    pub fn migrate(path: &Path, password: &str) -> Result<Self, RegistryError> {
+        let (storage, contents) = Storage::migrate(path, password)?;
+        Self::from_contents(storage, Some(&contents))
+    }
+
+    fn from_contents(storage: Storage, contents: Option<&[u8]>) -> Result<Self, RegistryError> {
+        let file = match contents {
+            Some(contents) => serde_json::from_slice::<RegistryFile>(contents)?,
+            None => RegistryFile::default(),
+        };
+        Ok(Self {
+            storage,
+            wallets: file.wallets,
+            last_opened: file.last_opened,
+            contacts: file.contacts,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), RegistryError> {
+        let file = RegistryFile {
+            wallets: self.wallets.clone(),
+            last_opened: self.last_opened.clone(),
+            contacts: self.contacts.clone(),
+        };
+        self.storage.write(&serde_json::to_vec_pretty(&file)?)?;
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.wallets.iter().map(|w| w.name.clone()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WalletEntry> {
+        self.wallets.iter().find(|w| w.name == name)
+    }
+
+    /// The wallet to open at startup: the one used last, or the first one saved.
+    pub fn last_opened(&self) -> Option<&WalletEntry> {
+        self.last_opened
+            .as_ref()
+            .and_then(|name| self.get(name))
+            .or_else(|| self.wallets.first())
+    }
+
+    pub fn set_last_opened(&mut self, name: &str) -> Result<(), RegistryError> {
+        if self.last_opened.as_deref() == Some(name) {
+            return Ok(());
+        }
+        self.last_opened = Some(name.to_string());
+        self.save()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wallets.is_empty()
+    }
+
+    /// Adds a new wallet and saves the registry.
+    pub fn add(&mut self, entry: WalletEntry) -> Result<(), RegistryError> {
+        if self.get(&entry.name).is_some() {
+            return Err(RegistryError::DuplicateName(entry.name));
+        }
+        self.wallets.push(entry);
+        self.save()
+    }
+
+    /// Replaces the wallet with the same name and saves the registry.
+    pub fn update(&mut self, entry: WalletEntry) -> Result<(), RegistryError> {
+        let existing = self
+            .wallets
+            .iter_mut()
+            .find(|w| w.name == entry.name)
+            .ok_or_else(|| RegistryError::NotFound(entry.name.clone()))?;
+        *existing = entry;
+        self.save()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), RegistryError> {
+        let len = self.wallets.len();
+        self.wallets.retain(|w| w.name != name);
+        if self.wallets.len() == len {
+            return Err(RegistryError::NotFound(name.to_string()));
+        }
+        if self.last_opened.as_deref() == Some(name) {
+            self.last_opened = None;
+        }
+        self.save()
+    }
+
+    /// The first "Wallet N" name that isn't taken.
+    pub fn next_name(&self) -> String {
+        (1..)
+            .map(|n| format!("Wallet {}", n))
+            .find(|name| self.get(name).is_none())
+            .expect("Ran out of wallet names")
+    }
+
+    /// The address book, shared by all wallets.
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
+    pub fn add_contact(&mut self, contact: Contact) -> Result<(), RegistryError> {
+        if self.contacts.iter().any(|c| c.name == contact.name) {
+            return Err(RegistryError::ContactExists(contact.name));
+        }
+        self.contacts.push(contact);
+        self.save()
+    }
+
+    pub fn remove_contact(&mut self, name: &str) -> Result<(), RegistryError> {
+        let len = self.contacts.len();
+        self.contacts.retain(|c| c.name != name);
+        if self.contacts.len() == len {
+            return Err(RegistryError::ContactNotFound(name.to_string()));
+        }
+        self.save()
+    }
+}
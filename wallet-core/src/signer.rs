@@ -1,19 +1,79 @@
-use std::fmt::Debug;
+use std::{fmt, fmt::Debug, sync::Mutex};
 
-use bdk::{bitcoin::{consensus::deserialize, util::psbt::PartiallySignedTransaction}, signer::Signer};
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::signer::{Signer, SignerError};
 use hwi::HWIDevice;
 use bdk::bitcoin::secp256k1::{Secp256k1, All};
 
+use crate::devices::DeviceError;
+use crate::psbt::{self, ImportedPsbt, PsbtError};
+
+#[derive(Debug)]
+pub enum HWISignerError {
+    Device(DeviceError),
+    Psbt(PsbtError),
+}
+
+impl fmt::Display for HWISignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HWISignerError::Device(err) => write!(f, "{}", err),
+            HWISignerError::Psbt(err) => write!(f, "Device returned a bad PSBT: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for HWISignerError {}
+
+impl From<DeviceError> for HWISignerError {
+    fn from(err: DeviceError) -> Self {
+        HWISignerError::Device(err)
+    }
+}
+
+impl From<PsbtError> for HWISignerError {
+    fn from(err: PsbtError) -> Self {
+        HWISignerError::Psbt(err)
+    }
+}
+
 pub struct HWISigner {
-    device: HWIDevice
+    device: HWIDevice,
+    // bdk's SignerError can't carry our reasons, so the last failure is kept here for callers
+    last_error: Mutex<Option<HWISignerError>>,
 }
 
 impl HWISigner {
     pub fn new(device: HWIDevice) -> Self {
         Self {
-            device
+            device,
+            last_error: Mutex::new(None),
         }
     }
+
+    /// Signs through HWI and only accepts the result if it's the same transaction with valid
+    /// signatures from this device.
+    pub fn sign_psbt(&self, psbt: &mut PartiallySignedTransaction) -> Result<(), HWISignerError> {
+        let hwipsbt = self
+            .device
+            .sign_tx(&psbt.clone(), true)
+            .map_err(DeviceError::from)?;
+        let signed = psbt::from_base64(&hwipsbt.psbt)?;
+        psbt::verify_signed(psbt, &signed, self.device.fingerprint)?;
+        *psbt = signed;
+        Ok(())
+    }
+
+    /// Signs a PSBT imported from another coordinator. HWI only speaks v0, so v2 PSBTs are
+    /// signed in their converted form and keep their version for export.
+    pub fn sign_imported(&self, imported: &mut ImportedPsbt) -> Result<(), HWISignerError> {
+        self.sign_psbt(&mut imported.psbt)
+    }
+
+    /// Takes the reason the last `Signer::sign` call failed, if it did.
+    pub fn take_error(&self) -> Option<HWISignerError> {
+        self.last_error.lock().expect("HWISigner error lock poisoned").take()
+    }
 }
 
 impl Debug for HWISigner {
@@ -25,29 +85,25 @@ impl Debug for HWISigner {
 impl Signer for HWISigner {
     fn sign(
         &self,
-        psbt: &mut bdk::bitcoin::util::psbt::PartiallySignedTransaction,
+        psbt: &mut PartiallySignedTransaction,
         _input_index: Option<usize>,
         _secp: &Secp256k1<All>,
-    ) -> Result<(), bdk::signer::SignerError> {
-        // Not sure how to sign partial?
-        match self.device.sign_tx(&psbt.clone(), true) {
-            Ok(hwipsbt) => { 
-                *psbt = deserialize_psbt_b64(&hwipsbt.psbt);
+    ) -> Result<(), SignerError> {
+        // HWI always signs every input it can, so this is only called once per PSBT
+        match self.sign_psbt(psbt) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let signer_error = match &err {
+                    HWISignerError::Device(DeviceError::UserRejected) => SignerError::UserCanceled,
+                    _ => SignerError::MissingKey,
+                };
+                *self.last_error.lock().expect("HWISigner error lock poisoned") = Some(err);
+                Err(signer_error)
             }
-            Err(_) => { eprintln!("Failed to sign tx. I'm gonna say UserCanceled because I don't know what the actual error is");
-            return Err(bdk::signer::SignerError::UserCanceled)}
         }
-
-        Ok(())
     }
 
     fn sign_whole_tx(&self) -> bool {
-        false 
+        true
     }
 }
-
-pub fn deserialize_psbt_b64(b64: &str) -> PartiallySignedTransaction {
-    let bytes = &base64::decode(b64).expect("Failed to decode base64")[..];
-    let psbt: PartiallySignedTransaction = deserialize(&bytes).expect("failed to deserialize psbt");
-    psbt
-}
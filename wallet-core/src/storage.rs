@@ -0,0 +1,245 @@
+//! Password encrypted files for everything we keep on disk.
+//!
+//! A file is `MAGIC || salt || nonce || ciphertext`. The key is derived from the password with
+//! Argon2id and the contents are sealed with ChaCha20-Poly1305, so a wrong password and a
+//! tampered file both fail to decrypt. Wallet databases are in memory for now, so the wallet
+//! registry is the only thing stored.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::Rng;
+use zeroize::Zeroize;
+
+const MAGIC: &[u8] = b"SLAPSENC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    EmptyPassword,
+    /// Decryption failed, the password is wrong or the file was modified
+    WrongPassword,
+    Corrupt,
+    Kdf,
+    /// The file was saved before encryption existed and has to be migrated explicitly
+    Unencrypted,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(err) => write!(f, "{}", err),
+            StorageError::EmptyPassword => write!(f, "Choose a password"),
+            StorageError::WrongPassword => write!(f, "Wrong password"),
+            StorageError::Corrupt => write!(f, "Encrypted file is truncated"),
+            StorageError::Kdf => write!(f, "Couldn't derive a key from the password"),
+            StorageError::Unencrypted => write!(f, "File isn't encrypted"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+/// An unlocked encrypted file. The derived key stays in memory until this is dropped, which
+/// is what locking does.
+#[derive(Clone)]
+pub struct Storage {
+    path: PathBuf,
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32], StorageError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|_| StorageError::Kdf)?;
+    Ok(key)
+}
+
+impl Storage {
+    pub fn exists(path: &Path) -> bool {
+        path.exists()
+    }
+
+    /// Whether the file at `path` was saved before encryption existed.
+    pub fn is_unencrypted(path: &Path) -> bool {
+        match fs::read(path) {
+            Ok(bytes) => !bytes.starts_with(MAGIC),
+            Err(_) => false,
+        }
+    }
+
+    /// Unlocks the file at `path` and returns its contents. A missing file is created on the
+    /// first write. Plaintext files are refused, they go through `migrate` instead.
+    pub fn open(path: &Path, password: &str) -> Result<(Self, Option<Vec<u8>>), StorageError> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) if bytes.starts_with(MAGIC) => bytes,
+            Ok(_) => return Err(StorageError::Unencrypted),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok((Self::create(path, password)?, None))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (storage, plaintext) = Self::decrypt(path, password, &bytes)?;
+        Ok((storage, Some(plaintext)))
+    }
+
+    /// Encrypts `plaintext` in the same format as the files we keep, for exports the user
+    /// saves somewhere else.
+    pub(crate) fn seal(password: &str, plaintext: &[u8]) -> Result<Vec<u8>, StorageError> {
+        Ok(Self::create(Path::new(""), password)?.encrypt(plaintext))
+    }
+
+    /// Decrypts what `seal` returned.
+    pub(crate) fn unseal(password: &str, bytes: &[u8]) -> Result<Vec<u8>, StorageError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(StorageError::Unencrypted);
+        }
+        let (_, plaintext) = Self::decrypt(Path::new(""), password, bytes)?;
+        Ok(plaintext)
+    }
+
+    fn decrypt(path: &Path, password: &str, bytes: &[u8]) -> Result<(Self, Vec<u8>), StorageError> {
+        let header = MAGIC.len() + SALT_LEN + NONCE_LEN;
+        if bytes.len() < header {
+            return Err(StorageError::Corrupt);
+        }
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&bytes[MAGIC.len()..MAGIC.len() + SALT_LEN]);
+        let nonce = Nonce::from_slice(&bytes[MAGIC.len() + SALT_LEN..header]);
+
+        let storage = Self {
+            path: path.to_path_buf(),
+            salt,
+            key: derive_key(password, &salt)?,
+        };
+        let plaintext = storage
+            .cipher()
+            .decrypt(nonce, &bytes[header..])
+            .map_err(|_| StorageError::WrongPassword)?;
+        Ok((storage, plaintext))
+    }
+
+    /// Encrypts a file saved before encryption existed with `password`, rewriting it straight
+    /// away so the plaintext doesn't stay on disk. Only call this once the user has agreed to it.
+    pub fn migrate(path: &Path, password: &str) -> Result<(Self, Vec<u8>), StorageError> {
+        let plaintext = fs::read(path)?;
+        if plaintext.starts_with(MAGIC) {
+            let (storage, contents) = Self::open(path, password)?;
+            return Ok((storage, contents.unwrap_or_default()));
+        }
+        let storage = Self::create(path, password)?;
+        storage.write(&plaintext)?;
+        Ok((storage, plaintext))
+    }
+
+    fn create(path: &Path, password: &str) -> Result<Self, StorageError> {
+        if password.is_empty() {
+            return Err(StorageError::EmptyPassword);
+        }
+        let salt: [u8; SALT_LEN] = rand::thread_rng().gen();
+        Ok(Self {
+            path: path.to_path_buf(),
+            salt,
+            key: derive_key(password, &salt)?,
+        })
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.key))
+    }
+
+    /// `MAGIC || salt || nonce || ciphertext` with a fresh nonce.
+    fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::thread_rng().gen();
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .expect("ChaCha20-Poly1305 encryption can't fail");
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&self.salt);
+        bytes.extend(&nonce);
+        bytes.extend(ciphertext);
+        bytes
+    }
+
+    /// Encrypts `plaintext` and replaces the file.
+    pub fn write(&self, plaintext: &[u8]) -> Result<(), StorageError> {
+        let bytes = self.encrypt(plaintext);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first so a crash can't leave half a registry behind
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Storage").field("path", &self.path).finish()
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let suffix: u64 = rand::thread_rng().gen();
+        std::env::temp_dir().join(format!("slaps-{}-{:x}.json", name, suffix))
+    }
+
+    #[test]
+    fn round_trips_and_rejects_wrong_password() {
+        let path = temp_path("roundtrip");
+        let (storage, contents) = Storage::open(&path, "hunter2").unwrap();
+        assert!(contents.is_none());
+        storage.write(b"{}").unwrap();
+
+        let (_, contents) = Storage::open(&path, "hunter2").unwrap();
+        assert_eq!(contents.unwrap(), b"{}");
+        assert!(matches!(Storage::open(&path, "hunter3"), Err(StorageError::WrongPassword)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn migrates_plaintext_only_when_asked() {
+        let path = temp_path("migrate");
+        fs::write(&path, b"{\"wallets\":[]}").unwrap();
+        assert!(Storage::is_unencrypted(&path));
+        assert!(matches!(Storage::open(&path, "hunter2"), Err(StorageError::Unencrypted)));
+
+        let (_, contents) = Storage::migrate(&path, "hunter2").unwrap();
+        assert_eq!(contents, b"{\"wallets\":[]}");
+        assert!(!Storage::is_unencrypted(&path));
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+
+        let (_, contents) = Storage::open(&path, "hunter2").unwrap();
+        assert_eq!(contents.unwrap(), b"{\"wallets\":[]}");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,141 +1,409 @@
-use std::{str::FromStr, sync::Arc};
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::Arc};
 
-use base64;
-
-use bdk::{ScriptType, database::MemoryDatabase, signer::{SignerId, SignerOrdering}};
+use bdk::{ScriptType as KeychainKind, database::{Database, MemoryDatabase}, signer::{SignerId, SignerOrdering}};
 use bdk::electrum_client::Client;
 use bdk::{
-    bitcoin::{
-        consensus::deserialize,
-        util::{
-            bip32::{DerivationPath, ExtendedPubKey, Fingerprint},
-            psbt::PartiallySignedTransaction,
-        },
-    },
+    bitcoin::util::bip32::{ChildNumber, DerivationPath, Fingerprint},
     blockchain::{noop_progress, ElectrumBlockchain},
-    descriptor::{get_checksum, Descriptor, MiniscriptKey},
+    descriptor::{Descriptor, MiniscriptKey},
     miniscript::DescriptorPublicKey,
     signer::Signer,
 };
 use bdk::{FeeRate, TxBuilder, Wallet};
 
-use bdk::bitcoin::{consensus::serialize, Address, Network};
+use bdk::bitcoin::{util::psbt::PartiallySignedTransaction, Address, Network};
 use hwi::HWIDevice;
+use hwi::types::HWIAddressType;
+
+use crate::{
+    address::{self, AddressError},
+    descriptor::{self, DescriptorBuilder, DescriptorError, Multisig, ScriptType, SlapsSigner},
+    devices::RegistrationStatus,
+    labels::LabelStore,
+    psbt,
+    registry::{MultisigEntry, WalletEntry},
+    signer::HWISignerError,
+    ArcStr, DeviceError, HWISigner, SlapsDevice, SlapsDevices,
+};
 
-use crate::{ArcStr, HWISigner, SlapsDevice};
+#[derive(Debug)]
+pub enum WalletError {
+    Bdk(bdk::Error),
+    Signing(HWISignerError),
+    Device(DeviceError),
+    Descriptor(DescriptorError),
+    Address(AddressError),
+}
 
-pub struct SlapsWallet {
-    descriptor: ArcStr,
-    change_descriptor: ArcStr,
-    pub signer_fingerprint: Option<Fingerprint>,
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::Bdk(err) => write!(f, "Wallet error: {:?}", err),
+            WalletError::Signing(err) => write!(f, "Signing failed: {}", err),
+            WalletError::Device(err) => write!(f, "{}", err),
+            WalletError::Descriptor(err) => write!(f, "{}", err),
+            WalletError::Address(err) => write!(f, "{}", err),
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-struct SlapsSigner {
-    pub xpub: Arc<ExtendedPubKey>,
-    pub fingerprint: Arc<Fingerprint>,
-    pub derivation_path: Arc<DerivationPath>,
+impl std::error::Error for WalletError {}
+
+impl From<bdk::Error> for WalletError {
+    fn from(err: bdk::Error) -> Self {
+        WalletError::Bdk(err)
+    }
 }
 
-impl SlapsSigner {
-    pub fn new(
-        fingerprint: Fingerprint,
-        derivation_path: DerivationPath,
-        xpub: ExtendedPubKey,
-    ) -> Self {
-        Self {
-            fingerprint: Arc::new(fingerprint),
-            derivation_path: Arc::new(derivation_path),
-            xpub: Arc::new(xpub),
-        }
+impl From<HWISignerError> for WalletError {
+    fn from(err: HWISignerError) -> Self {
+        WalletError::Signing(err)
     }
 }
 
-fn create_descriptor(
-    derivation_path: DerivationPath,
-    fingerprint: Fingerprint,
-    xpub: ExtendedPubKey,
-    index: Option<u32>,
-    change: bool,
-    checksum: bool,
-) -> Result<Descriptor<DescriptorPublicKey>, bdk::miniscript::Error> {
-    let origin_prefix = derivation_path
-        .to_string()
-        .replace("m", &fingerprint.to_string());
-
-    let descriptor_part = format!("[{}]{}", origin_prefix, xpub.to_string());
-
-    let index_str = match index {
-        Some(index) => index.to_string(),
-        None => String::from("*"),
-    };
+impl From<DeviceError> for WalletError {
+    fn from(err: DeviceError) -> Self {
+        WalletError::Device(err)
+    }
+}
+
+impl From<DescriptorError> for WalletError {
+    fn from(err: DescriptorError) -> Self {
+        WalletError::Descriptor(err)
+    }
+}
+
+impl From<AddressError> for WalletError {
+    fn from(err: AddressError) -> Self {
+        WalletError::Address(err)
+    }
+}
 
-    let inner = format!("{}/{}/{}", descriptor_part, change as u32, index_str);
+pub const DEFAULT_ELECTRUM_URL: &str = "tcp://localhost:51401";
 
-    let mut descriptor = format!("wpkh({})", inner);
+#[derive(Clone)]
+pub struct SlapsWallet {
+    pub name: ArcStr,
+    descriptor: ArcStr,
+    change_descriptor: ArcStr,
+    pub signer_fingerprints: Vec<Fingerprint>,
+    pub multisig: Option<Multisig>,
+    pub registrations: BTreeMap<Fingerprint, RegistrationStatus>,
+    pub labels: LabelStore,
+    network: Network,
+    electrum_url: ArcStr,
+}
+
+/// A transaction that spends from or pays to the wallet.
+#[derive(Debug, Clone)]
+pub struct HistoryItem {
+    pub txid: String,
+    pub received: u64,
+    pub sent: u64,
+    /// `None` while unconfirmed
+    pub height: Option<u32>,
+}
 
-    if let true = checksum {
-        descriptor = format!("{}#{}", descriptor, get_checksum(&descriptor).unwrap());
+#[derive(Debug, Clone)]
+pub struct UtxoItem {
+    /// `txid:vout`
+    pub outpoint: String,
+    pub value: u64,
+    pub address: Option<String>,
+}
+
+/// The full path of the key at `index` in a single key descriptor like
+/// `wpkh([d34db33f/84h/1h/0h]tpub.../0/*)`: the key's origin followed by its derivation steps,
+/// as miniscript parses them.
+fn derivation_path(descriptor: &str, index: u32) -> Option<DerivationPath> {
+    let descriptor = Descriptor::<DescriptorPublicKey>::from_str(descriptor).ok()?;
+    let xkey = match &descriptor {
+        Descriptor::Pkh(DescriptorPublicKey::XPub(xkey))
+        | Descriptor::Wpkh(DescriptorPublicKey::XPub(xkey))
+        | Descriptor::ShWpkh(DescriptorPublicKey::XPub(xkey)) => xkey,
+        _ => return None,
     };
+    let (_, origin_path) = xkey.origin.as_ref()?;
+    let mut path: Vec<ChildNumber> = origin_path
+        .as_ref()
+        .iter()
+        .chain(xkey.derivation_path.as_ref())
+        .cloned()
+        .collect();
+    if xkey.is_wildcard {
+        path.push(ChildNumber::from_normal_idx(index).ok()?);
+    }
+    Some(DerivationPath::from(path))
+}
 
-    Descriptor::from_str(&descriptor)
+fn address_type(script_type: ScriptType) -> HWIAddressType {
+    match script_type {
+        ScriptType::Pkh => HWIAddressType::Pkh,
+        ScriptType::ShWpkh => HWIAddressType::ShWpkh,
+        ScriptType::Wpkh => HWIAddressType::Wpkh,
+    }
 }
 
 impl SlapsWallet {
     pub fn new_empty() -> Self {
         Self {
+            name: "".into(),
             descriptor: "".into(),
             change_descriptor: "".into(),
-            signer_fingerprint: None,
+            signer_fingerprints: vec![],
+            multisig: None,
+            registrations: BTreeMap::new(),
+            labels: LabelStore::new(),
+            network: Network::Regtest,
+            electrum_url: DEFAULT_ELECTRUM_URL.into(),
         }
     }
 
-    pub fn new_from_hw_wallet(hw_wallet: &SlapsDevice) -> Self {
-        let hw_wallet = hw_wallet.get_device();
-        let is_testnet = true;
-        let derivation_path =
-            DerivationPath::from_str("m/84h/1h/0h").expect("Failed to create derivation path");
-        let fingerprint = hw_wallet.fingerprint;
-
-        let descriptors = hw_wallet.get_descriptors(None, true).expect("Couldn't get descriptors from HWI");
-        let descriptor = descriptors.receive[2].clone();
-        let change_descriptor = descriptors.internal[2].clone();
-        
-        // let xpub = hw_wallet
-        //     .get_xpub(&derivation_path, is_testnet)
-        //     .unwrap()
-        //     .xpub;
-        // let index = None;
-        // let change = false;
-        // let checksum = false;
-        // let descriptor = create_descriptor(
-        //     derivation_path.clone(),
-        //     fingerprint,
-        //     xpub,
-        //     index,
-        //     change,
-        //     checksum,
-        // )
-        // .expect("Failed to create descriptor");
-
-        // let change = true;
-        // let change_descriptor = create_descriptor(
-        //     derivation_path.clone(),
-        //     fingerprint,
-        //     xpub,
-        //     index,
-        //     change,
-        //     checksum,
-        // )
-        // .expect("Failed to create change descriptor");
-
-        // let signer = SlapsSigner::new(fingerprint, derivation_path, xpub);
+    pub fn new_from_hw_wallet(
+        hw_wallet: &SlapsDevice,
+        script_type: ScriptType,
+        account: u32,
+    ) -> Result<Self, WalletError> {
+        let network = Network::Regtest;
+        let derivation_path = script_type.account_path(network, account);
+        let signer = hw_wallet.get_signer(derivation_path)?;
+
+        let builder = DescriptorBuilder::new(script_type, signer);
+        let descriptor = builder.clone().build()?;
+        let change_descriptor = builder.change(true).build()?;
+
+        Ok(Self {
+            name: "".into(),
+            descriptor: descriptor.into(),
+            change_descriptor: change_descriptor.into(),
+            signer_fingerprints: vec![hw_wallet.get_fingerprint()],
+            multisig: None,
+            registrations: BTreeMap::new(),
+            labels: LabelStore::new(),
+            network,
+            electrum_url: DEFAULT_ELECTRUM_URL.into(),
+        })
+    }
 
-        Self {
-            descriptor: descriptor.to_string().into(),
-            change_descriptor: change_descriptor.to_string().into(),
-            signer_fingerprint: Some(fingerprint),
+    /// A watch-only wallet from a receive and change descriptor pair. Keys with an origin can
+    /// still sign once their device is plugged in.
+    pub fn new_from_descriptors(
+        descriptor: &str,
+        change_descriptor: &str,
+        network: Network,
+    ) -> Result<Self, DescriptorError> {
+        let descriptor = descriptor::validate(descriptor)?;
+        let change_descriptor = descriptor::validate(change_descriptor)?;
+        descriptor::check_network(&descriptor, network)?;
+        descriptor::check_network(&change_descriptor, network)?;
+        let signer_fingerprints = descriptor::fingerprints(&descriptor);
+        let multisig = descriptor::sorted_multi(&descriptor);
+        let registrations = match multisig {
+            Some(_) => signer_fingerprints
+                .iter()
+                .map(|fingerprint| (*fingerprint, RegistrationStatus::Unregistered))
+                .collect(),
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            name: "".into(),
+            descriptor: descriptor.into(),
+            change_descriptor: change_descriptor.into(),
+            signer_fingerprints,
+            multisig,
+            registrations,
+            labels: LabelStore::new(),
+            network,
+            electrum_url: DEFAULT_ELECTRUM_URL.into(),
+        })
+    }
+
+    /// A watch-only single-sig wallet from an account xpub with its key origin. A SLIP-132
+    /// zpub or ypub picks the script type, plain xpubs default to `wpkh`.
+    pub fn new_from_xpub(key: &str, network: Network) -> Result<Self, DescriptorError> {
+        let signer: SlapsSigner = key.parse()?;
+        let xpub_start = key.find(']').map(|i| i + 1).unwrap_or(0);
+        let (_, script_type) = descriptor::parse_xpub(&key[xpub_start..])?;
+        let script_type = script_type.unwrap_or(ScriptType::Wpkh);
+        if (signer.xpub.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+            return Err(DescriptorError::WrongNetwork);
         }
+
+        let builder = DescriptorBuilder::new(script_type, signer);
+        Self::new_from_descriptors(&builder.clone().build()?, &builder.change(true).build()?, network)
+    }
+
+    /// A `threshold`-of-`keys.len()` `wsh(sortedmulti(...))` wallet.
+    pub fn new_multisig(threshold: usize, keys: &[SlapsSigner]) -> Result<Self, DescriptorError> {
+        let multisig = Multisig::new(threshold, keys.to_vec())?;
+        let signer_fingerprints: Vec<Fingerprint> = keys.iter().map(|key| *key.fingerprint).collect();
+        let registrations = signer_fingerprints
+            .iter()
+            .map(|fingerprint| (*fingerprint, RegistrationStatus::Unregistered))
+            .collect();
+
+        Ok(Self {
+            name: "".into(),
+            descriptor: multisig.descriptor(false, None)?.into(),
+            change_descriptor: multisig.descriptor(true, None)?.into(),
+            signer_fingerprints,
+            multisig: Some(multisig),
+            registrations,
+            labels: LabelStore::new(),
+            network: Network::Regtest,
+            electrum_url: DEFAULT_ELECTRUM_URL.into(),
+        })
+    }
+
+    /// Restores a wallet saved in the registry.
+    pub fn from_entry(entry: &WalletEntry) -> Result<Self, DescriptorError> {
+        let network = Network::from_str(&entry.network).map_err(|_| DescriptorError::WrongNetwork)?;
+        let mut wallet = Self::new_from_descriptors(&entry.descriptor, &entry.change_descriptor, network)?;
+
+        wallet.signer_fingerprints = entry
+            .fingerprints
+            .iter()
+            .map(|fingerprint| {
+                Fingerprint::from_str(fingerprint)
+                    .map_err(|_| DescriptorError::InvalidKey(fingerprint.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+        if let Some(multisig) = &entry.multisig {
+            let keys = multisig
+                .keys
+                .iter()
+                .map(|key| key.parse())
+                .collect::<Result<Vec<SlapsSigner>, _>>()?;
+            wallet.multisig = Some(Multisig::new(multisig.threshold, keys)?);
+        }
+        for (fingerprint, status) in &entry.registrations {
+            let fingerprint = Fingerprint::from_str(fingerprint)
+                .map_err(|_| DescriptorError::InvalidKey(fingerprint.clone()))?;
+            wallet.registrations.insert(fingerprint, *status);
+        }
+        for label in &entry.labels {
+            wallet.labels.insert(label.clone());
+        }
+        wallet.name = entry.name.as_str().into();
+        wallet.electrum_url = entry.backend.as_str().into();
+        Ok(wallet)
+    }
+
+    /// What the registry saves for this wallet.
+    pub fn to_entry(&self) -> WalletEntry {
+        WalletEntry {
+            name: self.name.to_string(),
+            descriptor: self.descriptor.to_string(),
+            change_descriptor: self.change_descriptor.to_string(),
+            network: self.network.to_string(),
+            backend: self.electrum_url.to_string(),
+            fingerprints: self.signer_fingerprints.iter().map(|f| f.to_string()).collect(),
+            multisig: self.multisig.as_ref().map(|multisig| MultisigEntry {
+                threshold: multisig.threshold,
+                keys: multisig.keys.iter().map(|key| key.to_string()).collect(),
+            }),
+            registrations: self
+                .registrations
+                .iter()
+                .map(|(fingerprint, status)| (fingerprint.to_string(), *status))
+                .collect(),
+            labels: self.labels.iter().cloned().collect(),
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// The plugged in devices that hold a key for this wallet.
+    pub fn connected_signers(&self, devices: &SlapsDevices) -> Vec<SlapsDevice> {
+        self.signer_fingerprints
+            .iter()
+            .filter_map(|fingerprint| devices.get_device_by_fingerprint(*fingerprint))
+            .cloned()
+            .collect()
+    }
+
+    /// Registers this multisig wallet on `device` and reports how far that got: registered
+    /// on devices that keep the policy, otherwise only the address was checked. Pass the
+    /// result to `record_registration` to keep it.
+    pub fn register_on_device(
+        &self,
+        device: &SlapsDevice,
+    ) -> Result<(ArcStr, RegistrationStatus), DeviceError> {
+        let multisig = self.multisig.as_ref().ok_or_else(|| {
+            DeviceError::UnsupportedInput("only multisig wallets need registering".into())
+        })?;
+        let address = device.register_multisig(multisig)?;
+        let status = if device.keeps_multisig_policy() {
+            RegistrationStatus::Registered
+        } else {
+            RegistrationStatus::AddressVerified
+        };
+        Ok((address, status))
+    }
+
+    /// Records a registration from `register_on_device` for the signer with `fingerprint`.
+    /// Returns the status now on record.
+    pub fn record_registration(
+        &mut self,
+        fingerprint: Fingerprint,
+        status: RegistrationStatus,
+    ) -> RegistrationStatus {
+        let recorded = self
+            .registrations
+            .entry(fingerprint)
+            .or_insert(RegistrationStatus::Unregistered);
+        // Showing the address again doesn't undo an earlier registration
+        if *recorded != RegistrationStatus::Registered {
+            *recorded = status;
+        }
+        *recorded
+    }
+
+    /// The Coldcard setup file for this multisig wallet.
+    pub fn export_registration_file(&self, name: &str) -> Option<String> {
+        Some(self.multisig.as_ref()?.coldcard_file(name))
+    }
+
+    /// Records that the setup file was saved, for the plugged in Coldcards among the wallet's
+    /// signers. Other devices can't import the file and unplugged ones can't be told apart, so
+    /// those are left as they are. Returns the fingerprints marked.
+    pub fn mark_registration_file_exported(&mut self, devices: &SlapsDevices) -> Vec<Fingerprint> {
+        let mut marked = vec![];
+        for device in self.connected_signers(devices) {
+            if !device.needs_registration_file() {
+                continue;
+            }
+            let status = self
+                .registrations
+                .entry(device.get_fingerprint())
+                .or_insert(RegistrationStatus::Unregistered);
+            if *status == RegistrationStatus::Unregistered {
+                *status = RegistrationStatus::FileExported;
+                marked.push(device.get_fingerprint());
+            }
+        }
+        marked
+    }
+
+    /// The script type of a single-sig wallet, `None` for multisig.
+    pub fn script_type(&self) -> Option<ScriptType> {
+        ScriptType::from_descriptor(&self.descriptor)
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn descriptor(&self) -> &str {
+        &self.descriptor
+    }
+
+    pub fn change_descriptor(&self) -> &str {
+        &self.change_descriptor
     }
 
     pub fn print_descriptors(&self) {
@@ -148,13 +416,13 @@ impl SlapsWallet {
         let descriptor: &str = &self.descriptor.clone();
         let change_descriptor: &str = &self.change_descriptor.clone();
         let database = MemoryDatabase::default();
-        let client = Client::new("tcp://localhost:51401")?;
+        let client = Client::new(&self.electrum_url)?;
 
         // TODO: actually use change descriptor
         let wallet = Wallet::new(
             descriptor,
             None,
-            Network::Regtest,
+            self.network,
             database,
             ElectrumBlockchain::from(client),
         )?;
@@ -164,10 +432,31 @@ impl SlapsWallet {
         Ok(wallet)
     }
 
-    pub fn get_address(&self) -> String {
+    /// Returns a new receive address along with its derivation index.
+    pub fn get_address(&self) -> (String, u32) {
         let wallet = self.create_wallet().expect("Failed to create wallet");
         let address = wallet.get_new_address().expect("Couldn't get an address");
-        address.to_string()
+        let (_, index) = wallet
+            .database()
+            .get_path_from_script_pubkey(&address.script_pubkey())
+            .expect("Failed to read the database")
+            .expect("New address isn't in the database");
+        (address.to_string(), index)
+    }
+
+    /// Asks `device` to show the receive address at `index` and checks it matches `address`.
+    pub fn verify_address_on_device(
+        &self,
+        device: &SlapsDevice,
+        address: &str,
+        index: u32,
+    ) -> Result<bool, DeviceError> {
+        let unsupported = || DeviceError::Other("This wallet type can't be shown on the device".into());
+        let path = derivation_path(&self.descriptor, index).ok_or_else(unsupported)?;
+        let address_type = self.script_type().map(address_type).ok_or_else(unsupported)?;
+
+        let shown = device.display_address(&path, address_type)?;
+        Ok(&*shown == address)
     }
 
     pub fn get_balance(&self) -> u64 {
@@ -179,45 +468,91 @@ impl SlapsWallet {
         balance
     }
 
-    pub fn create_and_print_tx(&self, address: String, device: HWIDevice) {
-        let mut wallet = self.create_wallet().expect("Failed to create wallet");
-        let send_to = Address::from_str(&address).expect("Failed to parse address string");
-
-        let (psbt, details) = wallet
-            .create_tx(
-                TxBuilder::with_recipients(vec![(send_to.script_pubkey(), 50_000)])
-            )
-            .expect("Failed to build Tx");
-
-        println!("Transaction details: {:#?}", details);
-        println!("Unsigned PSBT: {}", base64::encode(&serialize(&psbt)));
-
-        let signer = HWISigner::new(device.clone());
+    /// Syncs the wallet and lists its transactions, newest first, and unspent outputs.
+    pub fn history(&self) -> Result<(Vec<HistoryItem>, Vec<UtxoItem>), WalletError> {
+        let wallet = self.create_wallet()?;
+
+        let mut transactions: Vec<HistoryItem> = wallet
+            .list_transactions(false)?
+            .into_iter()
+            .map(|tx| HistoryItem {
+                txid: tx.txid.to_string(),
+                received: tx.received,
+                sent: tx.sent,
+                height: tx.height,
+            })
+            .collect();
+        transactions.sort_by_key(|tx| std::cmp::Reverse(tx.height.unwrap_or(u32::MAX)));
+
+        let utxos = wallet
+            .list_unspent()?
+            .into_iter()
+            .map(|utxo| UtxoItem {
+                outpoint: utxo.outpoint.to_string(),
+                value: utxo.txout.value,
+                address: Address::from_script(&utxo.txout.script_pubkey, self.network)
+                    .map(|address| address.to_string()),
+            })
+            .collect();
+
+        Ok((transactions, utxos))
+    }
 
-        wallet.add_signer(ScriptType::Internal, device.fingerprint.into(), SignerOrdering(100), Arc::new(signer));
+    /// An unsigned PSBT paying `amount` sats, for signers we can't reach over HWI.
+    pub fn create_psbt(&self, address: &str, amount: u64) -> Result<PartiallySignedTransaction, WalletError> {
+        let wallet = self.create_wallet()?;
+        self.build_psbt(&wallet, address, amount)
+    }
 
+    fn build_psbt(
+        &self,
+        wallet: &Wallet<ElectrumBlockchain, MemoryDatabase>,
+        address: &str,
+        amount: u64,
+    ) -> Result<PartiallySignedTransaction, WalletError> {
+        let send_to = address::parse_address(address, self.network)?;
 
-        // let hwi_psbt = device
-        //     .sign_tx(&psbt, true)
-        //     .expect("Failed to sign transaction");
+        let (psbt, details) = wallet.create_tx(
+            TxBuilder::with_recipients(vec![(send_to.script_pubkey(), amount)])
+        )?;
 
-        // let signed_psbt = deserialize_psbt_b64(&hwi_psbt.psbt);
+        println!("Transaction details: {:#?}", details);
+        println!("Unsigned PSBT: {}", psbt::to_base64(&psbt));
+        Ok(psbt)
+    }
 
-        let (signed_psbt, finalized) = wallet.sign(psbt, None).expect("Failed to sign Tx");
-        // let (finalized_psbt, finalized) = wallet.finalize_psbt(signed_psbt, None).expect("Failed to finalize psbt");
+    /// Builds a transaction paying `amount` sats and signs it with each of `devices` in turn.
+    /// Once the signatures finalize it, the transaction is broadcast. Returns the signed PSBT
+    /// and whether it was finalized.
+    pub fn create_and_sign_tx(
+        &self,
+        address: String,
+        amount: u64,
+        devices: Vec<HWIDevice>,
+    ) -> Result<(PartiallySignedTransaction, bool), WalletError> {
+        let mut wallet = self.create_wallet()?;
+        let psbt = self.build_psbt(&wallet, &address, amount)?;
+
+        let mut signers = vec![];
+        for (index, device) in devices.iter().enumerate() {
+            let signer = Arc::new(HWISigner::new(device.clone()));
+            let ordering = SignerOrdering(100 + index);
+            wallet.add_signer(KeychainKind::Internal, device.fingerprint.into(), ordering, signer.clone());
+            signers.push(signer);
+        }
 
-        // assert!(finalized, "Transaction is not finalized!");
+        let (signed_psbt, finalized) = match wallet.sign(psbt, None) {
+            Ok(result) => result,
+            Err(err) => {
+                let signer_error = signers.iter().find_map(|signer| signer.take_error());
+                return Err(signer_error.map(WalletError::from).unwrap_or_else(|| err.into()));
+            }
+        };
 
-        //println!("Signed PSBT: {}", signed_psbt);
+        if finalized {
+            wallet.broadcast(signed_psbt.clone().extract_tx())?;
+        }
 
-        // wallet
-        //    .broadcast(finalized_psbt.extract_tx())
-        //    .expect("Failed to broadcast");
+        Ok((signed_psbt, finalized))
     }
 }
-
-pub fn deserialize_psbt_b64(b64: &str) -> PartiallySignedTransaction {
-    let bytes = &base64::decode(b64).expect("Failed to decode bytes")[..];
-    let psbt: PartiallySignedTransaction = deserialize(&bytes).expect("Failed to deserialize psbt");
-    psbt
-}
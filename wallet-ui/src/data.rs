@@ -1,20 +1,34 @@
 use async_std::task;
-use druid::{Application, ArcStr, Data, Env, EventCtx, ExtEventSink, Lens, Target};
+use druid::{commands, Application, ArcStr, Data, Env, EventCtx, ExtEventSink, FileDialogOptions, Lens, Target};
 use druid::im::{vector, Vector};
-use std::{sync::Arc, time::Duration};
-use wallet_core::{SlapsDevice, SlapsDevices, SlapsWallet};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use wallet_core::address_book::Contact;
+use wallet_core::backup::WalletBackup;
+use wallet_core::bip21::{self, PaymentUri};
+use wallet_core::bsms::{Coordinator, EncryptionLevel};
+use wallet_core::export::{self, ExportFormat};
+use wallet_core::import;
+use wallet_core::labels::LabelType;
+use wallet_core::psbt::{self, PartiallySignedTransaction};
+use wallet_core::psbt_qr::{self, AnimatedFormat, Progress};
+use wallet_core::{
+    DescriptorError, DeviceError, HistoryItem, RegistrationStatus, RegistryError, ScriptType, SlapsDevice, SlapsDevices, SlapsSigner,
+    SlapsWallet, Storage, UtxoItem, WalletRegistry,
+};
 
 use crate::selectors;
 
-#[derive(Clone, Data)]
+#[derive(Clone, Data, Lens)]
 pub struct UIDevice {
-    device: Arc<SlapsDevice>
+    device: Arc<SlapsDevice>,
+    account: f64,
 }
 
 impl UIDevice {
     fn new(device: &SlapsDevice) -> Self {
         Self {
-            device: Arc::new(device.clone())
+            device: Arc::new(device.clone()),
+            account: 0.0,
         }
     }
 
@@ -30,51 +44,363 @@ impl UIDevice {
         format!("Path: {}", data.device.get_path())
     }
 
-    pub fn print_xpub(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
-        println!("xpub: {}", data.device.get_xpub());
+    pub fn display_account(data: &Self, _env: &Env) -> String {
+        format!("Account: {}", data.account as u32)
+    }
+
+    pub fn print_xpub(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        match data.device.get_xpub(data.account as u32) {
+            Ok(xpub) => println!("xpub: {}", xpub),
+            Err(err) => ctx.submit_command(selectors::UPDATE_SETUP_STATUS.with(err.to_string())),
+        }
     }
 
     pub fn create_wallet(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
         let hwi_device = data.device.clone();
-        ctx.submit_command(selectors::CREATE_WALLET.with(hwi_device));
+        ctx.submit_command(selectors::CREATE_WALLET.with((hwi_device, data.account as u32)));
+    }
+
+    pub fn add_to_multisig(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let hwi_device = data.device.clone();
+        ctx.submit_command(selectors::ADD_MULTISIG_DEVICE.with((hwi_device, data.account as u32)));
+    }
+
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct UITransaction {
+    txid: ArcStr,
+    summary: ArcStr,
+    label: String,
+}
+
+impl UITransaction {
+    pub fn save_label(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(selectors::SET_LABEL.with((LabelType::Tx, data.txid.clone(), data.label.clone())));
+    }
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct UIUtxo {
+    outpoint: ArcStr,
+    summary: ArcStr,
+    label: String,
+}
+
+impl UIUtxo {
+    pub fn save_label(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(selectors::SET_LABEL.with((LabelType::Output, data.outpoint.clone(), data.label.clone())));
     }
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct UIContact {
+    name: ArcStr,
+    address: ArcStr,
+    notes: ArcStr,
+}
+
+impl UIContact {
+    pub fn select(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(selectors::SELECT_CONTACT.with(data.address.clone()));
+    }
+
+    pub fn delete(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(selectors::DELETE_CONTACT.with(data.name.clone()));
+    }
+}
+
+/// The script type picked on the Setup route for wallets created from a device
+#[derive(Clone, Copy, PartialEq, Data)]
+pub enum WalletType {
+    Legacy,
+    NestedSegwit,
+    NativeSegwit,
+}
 
+impl WalletType {
+    fn script_type(self) -> ScriptType {
+        match self {
+            WalletType::Legacy => ScriptType::Pkh,
+            WalletType::NestedSegwit => ScriptType::ShWpkh,
+            WalletType::NativeSegwit => ScriptType::Wpkh,
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Data)]
 pub enum Route {
+    Unlock,
     Setup,
     Transactions,
     Send,
-    Receive
+    Receive,
+    History,
+    AddressBook,
+}
+
+/// How PSBTs are shown to air-gapped QR signers
+#[derive(Clone, Copy, PartialEq, Data)]
+pub enum QrFormat {
+    Ur,
+    Bbqr,
+}
+
+impl QrFormat {
+    fn animated_format(self) -> AnimatedFormat {
+        match self {
+            QrFormat::Ur => AnimatedFormat::Ur,
+            QrFormat::Bbqr => AnimatedFormat::Bbqr,
+        }
+    }
+}
+
+/// What a file picked in the open dialog should be imported as
+#[derive(Clone, Copy, PartialEq, Data)]
+pub enum FileImport {
+    BsmsRecord,
+    Backup,
+    WalletFile,
+    Labels,
+    /// A photo or screenshot of a signer's animated QR code, one frame per file
+    SignedPsbtQr,
+}
+
+/// What the contents waiting for the save dialog are
+#[derive(Clone, Copy, PartialEq, Data)]
+pub enum FileExport {
+    Labels,
+    RegistrationFile,
+    BsmsDescriptor,
+    Backup,
+    WalletFile,
+    SignedPsbt,
 }
 
 #[derive(Clone, Data, Lens)]
 pub struct AppState {
     wallet: Arc<SlapsWallet>,
+    /// `None` while locked
+    registry: Option<Arc<WalletRegistry>>,
+    registry_path: Arc<PathBuf>,
+    /// No registry has been saved yet, so unlocking sets the password
+    pub new_store: bool,
+    /// The registry was saved before encryption existed and needs migrating
+    pub unencrypted_store: bool,
+    confirm_migration: bool,
+    password: String,
+    password_confirm: String,
+    pub unlock_status: ArcStr,
+    wallet_names: Vector<ArcStr>,
+    new_wallet_name: String,
+    pub wallets_status: ArcStr,
     devices: Arc<SlapsDevices>,
     ui_device_list: Vector<UIDevice>,
+    wallet_type: WalletType,
+    pub setup_status: ArcStr,
     pub address: ArcStr,
+    pub address_index: Option<u32>,
+    pub address_verification: ArcStr,
+    address_label: String,
     pub balance: ArcStr,
     event_sink: Arc<ExtEventSink>,
     send_to_address: String,
+    /// In satoshis
+    send_amount: String,
+    /// Label and message from a pasted payment URI
+    pub send_note: ArcStr,
+    pub send_status: ArcStr,
+    /// In satoshis
+    receive_amount: String,
+    receive_label: String,
+    /// Only ever a `bitcoin:` URI, problems go in `payment_request_status`
+    pub payment_request: ArcStr,
+    pub payment_request_status: ArcStr,
+    /// Pixels per QR code module
+    qr_zoom: f64,
+    psbt_qr_format: QrFormat,
+    pub psbt_frames: Vector<ArcStr>,
+    /// The PSBT shown as QR frames, kept to check what the signer sends back
+    unsigned_psbt: Option<Arc<PartiallySignedTransaction>>,
+    /// The verified PSBT read back from a QR signer, finalized if it has all its signatures
+    pub signed_psbt: Option<Arc<PartiallySignedTransaction>>,
+    scanned_frames: Vector<ArcStr>,
+    pub psbt_qr_status: ArcStr,
+    multisig_threshold: f64,
+    multisig_total: f64,
+    multisig_keys: Vector<ArcStr>,
+    multisig_key_input: String,
+    pub multisig_status: ArcStr,
+    pub registration_status: ArcStr,
+    pub export_status: ArcStr,
+    /// Encrypts exported backups and decrypts restored ones
+    backup_password: String,
+    transactions: Vector<UITransaction>,
+    utxos: Vector<UIUtxo>,
+    pub history_status: ArcStr,
+    contacts: Vector<UIContact>,
+    contact_name: String,
+    contact_address: String,
+    contact_notes: String,
+    pub address_book_status: ArcStr,
+    /// Contents written out when the save dialog returns a path
+    pub pending_export: Option<(FileExport, Arc<Vec<u8>>)>,
+    pub pending_import: Option<FileImport>,
+    bsms: Option<Arc<Coordinator>>,
+    pub bsms_status: ArcStr,
+    watch_only_descriptor: String,
+    watch_only_change_descriptor: String,
+    watch_only_xpub: String,
+    pub watch_only_status: ArcStr,
     pub active_route: Route
 }
 
 impl AppState {
-    pub fn new(sink: ExtEventSink) -> Self {
+    pub fn new(sink: ExtEventSink, registry_path: PathBuf) -> Self {
         Self {
             wallet: Arc::new(SlapsWallet::new_empty()),
+            registry: None,
+            new_store: !Storage::exists(&registry_path),
+            unencrypted_store: Storage::is_unencrypted(&registry_path),
+            confirm_migration: false,
+            registry_path: Arc::new(registry_path),
+            password: String::new(),
+            password_confirm: String::new(),
+            unlock_status: "".into(),
+            wallet_names: vector![],
+            new_wallet_name: String::new(),
+            wallets_status: "".into(),
             devices: Arc::new(SlapsDevices::new()),
             ui_device_list: vector![],
+            wallet_type: WalletType::NativeSegwit,
+            setup_status: "".into(),
             address: "".into(),
+            address_index: None,
+            address_verification: "".into(),
+            address_label: String::new(),
             balance: "0 satoshis".into(),
             event_sink: Arc::new(sink),
             send_to_address: String::new(),
-            active_route: Route::Setup
+            send_amount: String::new(),
+            send_note: "".into(),
+            send_status: "".into(),
+            receive_amount: String::new(),
+            receive_label: String::new(),
+            payment_request: "".into(),
+            payment_request_status: "".into(),
+            qr_zoom: 4.0,
+            psbt_qr_format: QrFormat::Ur,
+            psbt_frames: vector![],
+            unsigned_psbt: None,
+            signed_psbt: None,
+            scanned_frames: vector![],
+            psbt_qr_status: "".into(),
+            multisig_threshold: 2.0,
+            multisig_total: 3.0,
+            multisig_keys: vector![],
+            multisig_key_input: String::new(),
+            multisig_status: "".into(),
+            registration_status: "".into(),
+            export_status: "".into(),
+            backup_password: String::new(),
+            transactions: vector![],
+            utxos: vector![],
+            history_status: "".into(),
+            contacts: vector![],
+            contact_name: String::new(),
+            contact_address: String::new(),
+            contact_notes: String::new(),
+            address_book_status: "".into(),
+            pending_export: None,
+            pending_import: None,
+            bsms: None,
+            bsms_status: "".into(),
+            watch_only_descriptor: String::new(),
+            watch_only_change_descriptor: String::new(),
+            watch_only_xpub: String::new(),
+            watch_only_status: "".into(),
+            active_route: Route::Unlock
+        }
+    }
+
+    pub fn display_unlock_prompt(data: &Self, _env: &Env) -> String {
+        if data.unencrypted_store {
+            "Your wallet config isn't encrypted. Choose a password to encrypt it".into()
+        } else if data.new_store {
+            "Choose a password to encrypt your wallets".into()
+        } else {
+            "Enter your password to unlock your wallets".into()
         }
     }
 
+    /// Decrypts the registry in the background, since deriving the key takes a moment.
+    pub fn unlock(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let sets_password = data.new_store || data.unencrypted_store;
+        if sets_password && data.password != data.password_confirm {
+            data.unlock_status = "Passwords don't match".into();
+            return;
+        }
+        if data.unencrypted_store && !data.confirm_migration {
+            data.unlock_status = "Confirm migrating the unencrypted config first".into();
+            return;
+        }
+        let (new_store, migrate) = (data.new_store, data.unencrypted_store);
+        let password = std::mem::take(&mut data.password);
+        data.password_confirm.clear();
+        data.unlock_status = "Unlocking...".into();
+
+        let path = data.registry_path.clone();
+        let sink = data.event_sink.clone();
+        task::spawn(async move {
+            let registry = if migrate {
+                WalletRegistry::migrate(&path, &password)
+            } else {
+                WalletRegistry::unlock(&path, &password).and_then(|registry| {
+                    // Saving straight away means the password is set even before the first wallet
+                    if new_store {
+                        registry.save()?;
+                    }
+                    Ok(registry)
+                })
+            };
+            let registry = registry.map(Arc::new).map_err(|err| err.to_string());
+            sink.submit_command(selectors::UNLOCKED, registry, Target::Auto)
+                .expect("Failed to send UNLOCKED command");
+        });
+    }
+
+    /// Opens the last used wallet once the registry is unlocked, skipping Setup if there is one.
+    pub fn set_unlocked(&mut self, registry: &Result<Arc<WalletRegistry>, String>) {
+        let registry = match registry {
+            Ok(registry) => registry.clone(),
+            Err(err) => {
+                // A plaintext file could have been dropped in place while we were locked
+                self.unencrypted_store = Storage::is_unencrypted(&self.registry_path);
+                self.unlock_status = err.as_str().into();
+                return;
+            }
+        };
+
+        self.new_store = false;
+        self.unencrypted_store = false;
+        self.confirm_migration = false;
+        self.unlock_status = "".into();
+        self.wallet_names = registry.names().into_iter().map(Into::into).collect();
+        let last_opened = registry.last_opened().map(|entry| entry.name.clone());
+        self.registry = Some(registry);
+        self.active_route = Route::Setup;
+        if let Some(name) = last_opened {
+            self.open_wallet(&name);
+        }
+    }
+
+    /// Forgets the decryption key and everything read from the registry.
+    pub fn lock(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let sink = (*data.event_sink).clone();
+        let registry_path = (*data.registry_path).clone();
+        *data = Self::new(sink, registry_path);
+    }
+
     pub fn refresh_devices(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
         Arc::make_mut(&mut data.devices).refresh();
         let device_list: Vector<UIDevice> = data.devices.list_devices().iter().map(|d| UIDevice::new(d)).collect();
@@ -89,33 +415,925 @@ impl AppState {
         let core = data.wallet.clone();
         let sink = data.event_sink.clone();
         task::spawn(async move {
-            let address: String = core.get_address().into();
+            let address = core.get_address();
             sink.submit_command(selectors::UPDATE_ADDRESS, address, Target::Auto)
                 .expect("Failed to send UPDATE_ADDRESS command");
         });
     }
 
+    pub fn set_address(&mut self, address: &str, index: u32) {
+        self.address = address.into();
+        self.address_index = Some(index);
+        self.address_verification = "".into();
+        self.payment_request = "".into();
+        self.payment_request_status = "".into();
+        self.address_label = self.wallet.labels.get(LabelType::Addr, address).unwrap_or("").to_string();
+    }
+
+    pub fn save_address_label(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        if data.address.is_empty() {
+            return;
+        }
+        let address = data.address.clone();
+        let label = data.address_label.clone();
+        data.set_label(LabelType::Addr, &address, &label);
+    }
+
+    /// Labels a transaction, address or output and saves it with the wallet.
+    pub fn set_label(&mut self, label_type: LabelType, reference: &str, label: &str) {
+        Arc::make_mut(&mut self.wallet).labels.set(label_type, reference, label.trim());
+        self.save_wallet();
+    }
+
+    pub fn refresh_history(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        data.history_status = "Syncing...".into();
+        let core = data.wallet.clone();
+        let sink = data.event_sink.clone();
+        task::spawn(async move {
+            let history = core.history().map_err(|err| err.to_string());
+            sink.submit_command(selectors::UPDATE_HISTORY, history, Target::Auto)
+                .expect("Failed to send UPDATE_HISTORY command");
+        });
+    }
+
+    pub fn set_history(&mut self, history: &Result<(Vec<HistoryItem>, Vec<UtxoItem>), String>) {
+        let (transactions, utxos) = match history {
+            Ok(history) => history,
+            Err(err) => {
+                self.history_status = err.as_str().into();
+                return;
+            }
+        };
+        self.history_status = "".into();
+        self.transactions = transactions
+            .iter()
+            .map(|tx| UITransaction {
+                txid: tx.txid.as_str().into(),
+                summary: format!(
+                    "{} {:+} sats, {}",
+                    tx.txid,
+                    tx.received as i64 - tx.sent as i64,
+                    tx.height.map_or("unconfirmed".to_string(), |height| format!("block {}", height)),
+                )
+                .into(),
+                label: String::new(),
+            })
+            .collect();
+        self.utxos = utxos
+            .iter()
+            .map(|utxo| UIUtxo {
+                outpoint: utxo.outpoint.as_str().into(),
+                summary: format!(
+                    "{} {} sats {}",
+                    utxo.outpoint,
+                    utxo.value,
+                    utxo.address.as_deref().unwrap_or("")
+                )
+                .into(),
+                label: String::new(),
+            })
+            .collect();
+        self.show_labels();
+    }
+
+    /// Fills the history lists' label boxes from the wallet's labels.
+    fn show_labels(&mut self) {
+        let labels = &self.wallet.labels;
+        for tx in self.transactions.iter_mut() {
+            tx.label = labels.get(LabelType::Tx, &tx.txid).unwrap_or("").to_string();
+        }
+        for utxo in self.utxos.iter_mut() {
+            utxo.label = labels.get(LabelType::Output, &utxo.outpoint).unwrap_or("").to_string();
+        }
+        if !self.address.is_empty() {
+            self.address_label = labels.get(LabelType::Addr, &self.address).unwrap_or("").to_string();
+        }
+    }
+
+    pub fn open_labels(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        data.pending_import = Some(FileImport::Labels);
+        ctx.submit_command(commands::SHOW_OPEN_PANEL.with(FileDialogOptions::new()));
+    }
+
+    fn import_labels(&mut self, contents: &str) {
+        match Arc::make_mut(&mut self.wallet).labels.import_jsonl(contents) {
+            Ok(imported) => {
+                self.history_status = format!("Imported {} labels", imported).into();
+                self.save_wallet();
+                self.show_labels();
+            }
+            Err(err) => self.history_status = err.to_string().into(),
+        }
+    }
+
+    /// Saves the wallet's labels as BIP329 JSON lines.
+    pub fn export_labels(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let labels = data.wallet.labels.export_jsonl();
+        data.save_file(ctx, FileExport::Labels, labels);
+    }
+
+    pub fn verify_address(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let index = match data.address_index {
+            Some(index) => index,
+            None => return,
+        };
+        let device = match data.connected_signers().into_iter().next() {
+            Some(device) => device,
+            None => {
+                data.address_verification = DeviceError::NotFound.to_string().into();
+                return;
+            }
+        };
+        data.address_verification = "Confirm the address on your device".into();
+
+        let core = data.wallet.clone();
+        let address = data.address.to_string();
+        let sink = data.event_sink.clone();
+        task::spawn(async move {
+            let status = match core.verify_address_on_device(&device, &address, index) {
+                Ok(true) => "Address verified on device".to_string(),
+                Ok(false) => "WARNING: the device shows a different address!".to_string(),
+                Err(err) => err.to_string(),
+            };
+            sink.submit_command(selectors::UPDATE_ADDRESS_VERIFICATION, status, Target::Auto)
+                .expect("Failed to send UPDATE_ADDRESS_VERIFICATION command");
+        });
+    }
+
     pub fn copy_address(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
         let address = data.address.to_string();
         Application::global().clipboard().put_string(address);
     }
 
     pub fn paste_send_address(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
-        if let Some(address) = Application::global().clipboard().get_string() {
-            data.send_to_address = address;
+        if let Some(contents) = Application::global().clipboard().get_string() {
+            data.fill_send_form(&contents);
+        }
+    }
+
+    /// Fills in the send form from an address or a BIP21 `bitcoin:` URI.
+    fn fill_send_form(&mut self, contents: &str) {
+        match PaymentUri::parse(contents, self.wallet.network()) {
+            Ok(payment) => {
+                self.send_to_address = payment.address.to_string();
+                if let Some(amount) = payment.amount {
+                    self.send_amount = amount.to_string();
+                }
+                let note: Vec<String> = payment.label.into_iter().chain(payment.message).collect();
+                self.send_note = note.join(": ").into();
+                self.send_status = "".into();
+            }
+            Err(err) => {
+                self.send_to_address = contents.trim().to_string();
+                self.send_note = "".into();
+                self.send_status = err.to_string().into();
+            }
+        }
+    }
+
+    /// A BIP21 URI for the current receive address with the optional amount and label.
+    pub fn create_payment_request(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let address = match wallet_core::address::parse_address(&data.address, data.wallet.network()) {
+            Ok(address) => address,
+            Err(_) => {
+                data.payment_request = "".into();
+                data.payment_request_status = "Get a new address first".into();
+                return;
+            }
+        };
+        let mut payment = PaymentUri::new(address);
+        if !data.receive_amount.trim().is_empty() {
+            match data.receive_amount.trim().parse::<u64>() {
+                Ok(amount) => payment.amount = Some(amount),
+                Err(_) => {
+                    data.payment_request = "".into();
+                    data.payment_request_status = "Amount must be a whole number of satoshis".into();
+                    return;
+                }
+            }
+        }
+        if !data.receive_label.trim().is_empty() {
+            payment.label = Some(data.receive_label.trim().to_string());
+        }
+        data.payment_request = payment.to_string().into();
+        data.payment_request_status = "".into();
+    }
+
+    /// What the receive QR code shows: the payment request if there is one, else the address.
+    pub fn qr_contents(&self) -> String {
+        if self.payment_request.starts_with("bitcoin:") {
+            self.payment_request.to_string()
+        } else {
+            self.address.to_string()
+        }
+    }
+
+    pub fn qr_module_size(&self) -> f64 {
+        self.qr_zoom
+    }
+
+    pub fn animated_psbt_frames(&self) -> Vector<ArcStr> {
+        self.psbt_frames.clone()
+    }
+
+    pub fn display_qr_zoom(data: &Self, _env: &Env) -> String {
+        format!("QR size: {}x", data.qr_zoom as u32)
+    }
+
+    pub fn copy_payment_request(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        if data.payment_request.starts_with("bitcoin:") {
+            Application::global().clipboard().put_string(data.payment_request.to_string());
+        }
+    }
+
+    pub fn display_send_amount(data: &Self, _env: &Env) -> String {
+        match data.send_amount.trim().parse::<u64>() {
+            Ok(amount) => format!("{} BTC", bip21::format_btc(amount)),
+            Err(_) => "".into(),
+        }
+    }
+
+    /// Whether the send address parses and is for the wallet's network.
+    pub fn send_address_valid(&self) -> bool {
+        wallet_core::address::parse_address(&self.send_to_address, self.wallet.network()).is_ok()
+    }
+
+    /// Checks the send address as it's typed.
+    pub fn display_send_address(data: &Self, _env: &Env) -> String {
+        if data.send_to_address.trim().is_empty() {
+            return "".into();
+        }
+        match wallet_core::address::parse_address(&data.send_to_address, data.wallet.network()) {
+            Ok(address) => format!("{} address", wallet_core::address::script_type(&address)),
+            Err(err) => err.to_string(),
         }
     }
 
     pub fn create_tx(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
         let core = data.wallet.clone();
         let address = data.send_to_address.clone();
-        // let sink = data.event_sink.clone();
-        let fingerprint = data.wallet.signer_fingerprint.expect("No signer fingerprint exists!");
-        let device = data.devices.get_device_by_fingerprint(fingerprint).get_device();
-        core.create_and_print_tx(address, device);
-        //task::spawn(async move {
-        //});
-        
+        let amount = match data.parse_send_amount() {
+            Some(amount) => amount,
+            None => return,
+        };
+        let devices: Vec<_> = data.connected_signers().iter().map(|d| d.get_device()).collect();
+        if devices.is_empty() {
+            data.send_status = DeviceError::NotFound.to_string().into();
+            return;
+        }
+        data.send_status = "Confirm the transaction on your devices".into();
+
+        let sink = data.event_sink.clone();
+        task::spawn(async move {
+            let result = core
+                .create_and_sign_tx(address, amount, devices)
+                .map(|(psbt, finalized)| (Arc::new(psbt), finalized))
+                .map_err(|err| err.to_string());
+            sink.submit_command(selectors::UPDATE_SIGNED_TX, result, Target::Auto)
+                .expect("Failed to send UPDATE_SIGNED_TX command");
+        });
+    }
+
+    pub fn set_signed_tx(&mut self, result: &Result<(Arc<PartiallySignedTransaction>, bool), String>) {
+        match result {
+            Ok((psbt, finalized)) => {
+                self.signed_psbt = Some(psbt.clone());
+                self.send_status = if *finalized {
+                    "Transaction signed and broadcast".into()
+                } else {
+                    "Transaction signed, save the signed PSBT to collect the remaining signatures".into()
+                };
+            }
+            Err(err) => self.send_status = err.as_str().into(),
+        }
+    }
+
+    fn parse_send_amount(&mut self) -> Option<u64> {
+        match self.send_amount.trim().parse::<u64>() {
+            Ok(amount) if amount > 0 => Some(amount),
+            _ => {
+                self.send_status = "Enter an amount in satoshis".into();
+                None
+            }
+        }
+    }
+
+    /// Creates the PSBT and shows it as an animated QR code for an air-gapped signer.
+    pub fn show_psbt_qr(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let amount = match data.parse_send_amount() {
+            Some(amount) => amount,
+            None => return,
+        };
+        data.psbt_qr_status = "Creating the PSBT".into();
+
+        let core = data.wallet.clone();
+        let address = data.send_to_address.clone();
+        let format = data.psbt_qr_format.animated_format();
+        let sink = data.event_sink.clone();
+        task::spawn(async move {
+            let result = match core.create_psbt(&address, amount) {
+                Ok(psbt) => psbt_qr::encode_psbt(&psbt, format)
+                    .map(|frames| (Arc::new(psbt), frames))
+                    .map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            sink.submit_command(selectors::UPDATE_PSBT_QR, result, Target::Auto)
+                .expect("Failed to send UPDATE_PSBT_QR command");
+        });
+    }
+
+    pub fn set_psbt_qr(
+        &mut self,
+        result: &Result<(Arc<PartiallySignedTransaction>, Vec<String>), String>,
+    ) {
+        match result {
+            Ok((psbt, frames)) => {
+                self.psbt_frames = frames.iter().map(|frame| frame.as_str().into()).collect();
+                self.unsigned_psbt = Some(psbt.clone());
+                self.signed_psbt = None;
+                self.scanned_frames.clear();
+                self.psbt_qr_status = "Scan with your signer, then open photos of its signed QR code".into();
+            }
+            Err(err) => self.psbt_qr_status = err.as_str().into(),
+        }
+    }
+
+    pub fn open_signed_psbt_qr(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        if data.unsigned_psbt.is_none() {
+            data.psbt_qr_status = "Show the PSBT to your signer first".into();
+            return;
+        }
+        data.pending_import = Some(FileImport::SignedPsbtQr);
+        ctx.submit_command(commands::SHOW_OPEN_PANEL.with(FileDialogOptions::new()));
+    }
+
+    /// Adds the QR codes in an image to the frames scanned so far and checks the PSBT once
+    /// they're all in.
+    fn import_signed_psbt_qr(&mut self, path: &std::path::Path) {
+        let codes = match psbt_qr::read_qr_codes(path) {
+            Ok(codes) => codes,
+            Err(err) => {
+                self.psbt_qr_status = err.to_string().into();
+                return;
+            }
+        };
+        self.scanned_frames.extend(codes.into_iter().map(ArcStr::from));
+        let frames: Vec<String> = self.scanned_frames.iter().map(|frame| frame.to_string()).collect();
+
+        let signed = match psbt_qr::decode_frames(&frames) {
+            Ok(Progress::Complete(signed)) => signed,
+            Ok(Progress::Partial { received, total }) => {
+                self.psbt_qr_status =
+                    format!("Read {} of {} frames, open the next image", received, total).into();
+                return;
+            }
+            Err(err) => {
+                self.scanned_frames.clear();
+                self.psbt_qr_status = err.to_string().into();
+                return;
+            }
+        };
+        self.scanned_frames.clear();
+
+        let unsigned = match &self.unsigned_psbt {
+            Some(unsigned) => unsigned,
+            None => return,
+        };
+        let verified = psbt::verify_cosigned(unsigned, &signed, &self.wallet.signer_fingerprints);
+        self.psbt_qr_status = match verified {
+            Ok(fingerprints) => {
+                let signers: Vec<String> = fingerprints.iter().map(|f| f.to_string()).collect();
+                let mut finalized = signed.clone();
+                let complete = psbt::finalize(&mut finalized).is_ok() && psbt::is_finalized(&finalized);
+                self.signed_psbt = Some(Arc::new(if complete { finalized } else { signed }));
+                format!(
+                    "Signed by {}{}",
+                    signers.join(", "),
+                    if complete { ", ready to broadcast" } else { ", needs more signatures" }
+                )
+                .into()
+            }
+            Err(err) => format!("Rejected the signed PSBT: {}", err).into(),
+        };
+    }
+
+    /// Saves the signed PSBT as a binary `.psbt` file for whatever broadcasts or cosigns it.
+    pub fn export_signed_psbt(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        if let Some(signed) = &data.signed_psbt {
+            let bytes = psbt::to_bytes(signed);
+            data.save_file(ctx, FileExport::SignedPsbt, bytes);
+        }
+    }
+
+    /// The plugged in devices that hold a key for the current wallet.
+    fn connected_signers(&self) -> Vec<SlapsDevice> {
+        self.wallet.connected_signers(&self.devices)
+    }
+
+    pub fn display_multisig_setup(data: &Self, _env: &Env) -> String {
+        format!(
+            "{}-of-{} multisig, {} keys added",
+            data.multisig_threshold as usize,
+            data.multisig_total as usize,
+            data.multisig_keys.len()
+        )
+    }
+
+    pub fn add_multisig_device(&mut self, device: Arc<SlapsDevice>, account: u32) {
+        match device.get_multisig_signer(account) {
+            Ok(key) => self.add_multisig_key(key),
+            Err(err) => self.multisig_status = err.to_string().into(),
+        }
+    }
+
+    pub fn add_pasted_multisig_key(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        match data.multisig_key_input.parse::<SlapsSigner>() {
+            Ok(key) => {
+                data.add_multisig_key(key);
+                data.multisig_key_input.clear();
+            }
+            Err(err) => data.multisig_status = err.to_string().into(),
+        }
+    }
+
+    fn add_multisig_key(&mut self, key: SlapsSigner) {
+        if self.multisig_keys.len() >= self.multisig_total as usize {
+            self.multisig_status = "All keys have already been added".into();
+            return;
+        }
+        self.multisig_keys.push_back(key.to_string().into());
+        self.multisig_status = "".into();
+    }
+
+    pub fn clear_multisig_keys(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        data.multisig_keys.clear();
+        data.multisig_status = "".into();
+    }
+
+    pub fn create_multisig_wallet(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let total = data.multisig_total as usize;
+        if data.multisig_keys.len() != total {
+            data.multisig_status = format!("Add {} keys first", total).into();
+            return;
+        }
+        let keys: Vec<SlapsSigner> = data
+            .multisig_keys
+            .iter()
+            .map(|key| key.parse().expect("Stored multisig keys are always valid"))
+            .collect();
+
+        match SlapsWallet::new_multisig(data.multisig_threshold as usize, &keys) {
+            Ok(wallet) => {
+                data.add_wallet(wallet);
+            }
+            Err(err) => data.multisig_status = err.to_string().into(),
+        }
+    }
+
+    pub fn display_registrations(data: &Self, _env: &Env) -> String {
+        data.wallet
+            .registrations
+            .iter()
+            .map(|(fingerprint, status)| format!("{}: {}", fingerprint, status))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn register_multisig(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let devices = data.connected_signers();
+        if devices.is_empty() {
+            data.registration_status = DeviceError::NotFound.to_string().into();
+            return;
+        }
+
+        data.registration_status = "Confirm the wallet on your devices".into();
+
+        let core = data.wallet.clone();
+        let sink = data.event_sink.clone();
+        task::spawn(async move {
+            let results = devices
+                .into_iter()
+                .filter(|device| !device.needs_registration_file())
+                .map(|device| {
+                    let result = core.register_on_device(&device).map_err(|err| err.to_string());
+                    (device, result)
+                })
+                .collect();
+            sink.submit_command(selectors::UPDATE_REGISTRATIONS, results, Target::Auto)
+                .expect("Failed to send UPDATE_REGISTRATIONS command");
+        });
+    }
+
+    pub fn set_registrations(
+        &mut self,
+        results: &[(SlapsDevice, Result<(ArcStr, RegistrationStatus), String>)],
+    ) {
+        let wallet = Arc::make_mut(&mut self.wallet);
+        let mut statuses = vec![];
+        for (device, result) in results {
+            let status = match result {
+                Ok((address, status)) => {
+                    let recorded = wallet.record_registration(device.get_fingerprint(), *status);
+                    format!("{}: {}, first address {}", device.get_fingerprint(), recorded, address)
+                }
+                Err(err) => format!("{}: {}", device.get_fingerprint(), err),
+            };
+            statuses.push(status);
+        }
+        self.registration_status = statuses.join("\n").into();
+        self.save_wallet();
+    }
+
+    pub fn export_registration_file(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        if let Some(file) = data.wallet.export_registration_file(&data.wallet.name) {
+            data.save_file(ctx, FileExport::RegistrationFile, file);
+        }
+    }
+
+    /// Records the Coldcard setup file as exported once it's actually on disk.
+    fn registration_file_saved(&mut self) {
+        let marked = Arc::make_mut(&mut self.wallet).mark_registration_file_exported(&self.devices);
+        self.registration_status = if marked.is_empty() {
+            "Saved the setup file. Plug in the Coldcards to record it was exported".into()
+        } else {
+            let marked: Vec<String> = marked.iter().map(|fingerprint| fingerprint.to_string()).collect();
+            format!("Saved the setup file for {}", marked.join(", ")).into()
+        };
+        self.save_wallet();
+    }
+
+    /// Asks where to save `contents`, which is written by `write_pending_export`.
+    fn save_file(&mut self, ctx: &mut EventCtx, kind: FileExport, contents: impl Into<Vec<u8>>) {
+        self.pending_export = Some((kind, Arc::new(contents.into())));
+        ctx.submit_command(commands::SHOW_SAVE_PANEL.with(FileDialogOptions::new()));
+    }
+
+    pub fn write_pending_export(&mut self, path: &std::path::Path) {
+        let (kind, contents) = match self.pending_export.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        if let Err(err) = std::fs::write(path, &*contents) {
+            let message: ArcStr = format!("Couldn't save {}: {}", path.display(), err).into();
+            match kind {
+                FileExport::Labels => self.history_status = message,
+                FileExport::RegistrationFile => self.registration_status = message,
+                FileExport::BsmsDescriptor => self.bsms_status = message,
+                FileExport::Backup | FileExport::WalletFile => self.export_status = message,
+                FileExport::SignedPsbt => self.psbt_qr_status = message,
+            }
+            return;
+        }
+        match kind {
+            FileExport::RegistrationFile => self.registration_file_saved(),
+            FileExport::Backup => self.export_status = "Saved the encrypted backup".into(),
+            FileExport::SignedPsbt => self.psbt_qr_status = "Saved the signed PSBT".into(),
+            _ => (),
+        }
+    }
+
+    pub fn create_watch_only_from_descriptors(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let wallet = SlapsWallet::new_from_descriptors(
+            &data.watch_only_descriptor,
+            &data.watch_only_change_descriptor,
+            data.wallet.network(),
+        );
+        data.set_watch_only_wallet(wallet);
+    }
+
+    pub fn create_watch_only_from_xpub(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let wallet = SlapsWallet::new_from_xpub(&data.watch_only_xpub, data.wallet.network());
+        data.set_watch_only_wallet(wallet);
+    }
+
+    fn set_watch_only_wallet(&mut self, wallet: Result<SlapsWallet, DescriptorError>) {
+        match wallet {
+            Ok(wallet) => {
+                self.watch_only_status = "".into();
+                self.add_wallet(wallet);
+            }
+            Err(err) => self.watch_only_status = err.to_string().into(),
+        }
+    }
+
+    pub fn read_pending_import(&mut self, path: &std::path::Path) {
+        // Images are read by the QR decoder, everything else is text
+        if self.pending_import == Some(FileImport::SignedPsbtQr) {
+            self.pending_import = None;
+            self.import_signed_psbt_qr(path);
+            return;
+        }
+        // Backups are usually encrypted
+        if self.pending_import == Some(FileImport::Backup) {
+            self.pending_import = None;
+            match std::fs::read(path) {
+                Ok(contents) => self.restore_backup(&contents),
+                Err(err) => {
+                    self.wallets_status = format!("Couldn't read {}: {}", path.display(), err).into();
+                }
+            }
+            return;
+        }
+        let kind = match self.pending_import.take() {
+            Some(kind) => kind,
+            None => return,
+        };
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                let message: ArcStr = format!("Couldn't read {}: {}", path.display(), err).into();
+                match kind {
+                    FileImport::BsmsRecord => self.bsms_status = message,
+                    FileImport::Labels => self.history_status = message,
+                    FileImport::WalletFile | FileImport::Backup => self.wallets_status = message,
+                    FileImport::SignedPsbtQr => self.psbt_qr_status = message,
+                }
+                return;
+            }
+        };
+        match kind {
+            FileImport::BsmsRecord => self.import_bsms_record(&contents),
+            FileImport::WalletFile => self.import_wallet_file(&contents),
+            FileImport::Labels => self.import_labels(&contents),
+            FileImport::SignedPsbtQr | FileImport::Backup => (),
+        }
+    }
+
+    pub fn start_bsms_session(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let coordinator = Coordinator::new(
+            data.multisig_threshold as usize,
+            data.multisig_total as usize,
+            EncryptionLevel::Standard,
+        );
+        data.bsms_status = format!("Share this token with each signer: {}", coordinator.token).into();
+        data.bsms = Some(Arc::new(coordinator));
+        data.multisig_keys.clear();
+    }
+
+    pub fn open_bsms_record(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        if data.bsms.is_none() {
+            data.bsms_status = "Start a BSMS session first".into();
+            return;
+        }
+        data.pending_import = Some(FileImport::BsmsRecord);
+        ctx.submit_command(commands::SHOW_OPEN_PANEL.with(FileDialogOptions::new()));
+    }
+
+    fn import_bsms_record(&mut self, contents: &str) {
+        let coordinator = match self.bsms.as_mut() {
+            Some(coordinator) => Arc::make_mut(coordinator),
+            None => return,
+        };
+        match coordinator.import_record(contents) {
+            Ok(record) => {
+                let key = record.key.clone();
+                self.bsms_status = format!("Imported and verified {}", record.description).into();
+                self.add_multisig_key(key);
+            }
+            Err(err) => self.bsms_status = err.to_string().into(),
+        }
+    }
+
+    pub fn export_bsms_descriptor(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let coordinator = match &data.bsms {
+            Some(coordinator) => coordinator,
+            None => return,
+        };
+        match coordinator.descriptor_record(data.wallet.network()) {
+            Ok(record) => data.save_file(ctx, FileExport::BsmsDescriptor, record),
+            Err(err) => data.bsms_status = err.to_string().into(),
+        }
+    }
+
+    pub fn export_backup(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        if data.backup_password.is_empty() {
+            data.export_status = "Choose a password to encrypt the backup with".into();
+            return;
+        }
+        let backup = WalletBackup::new(&data.wallet).encrypt(&data.backup_password);
+        data.backup_password.clear();
+        match backup {
+            Ok(backup) => data.save_file(ctx, FileExport::Backup, backup),
+            Err(err) => data.export_status = err.to_string().into(),
+        }
+    }
+
+    /// Saves the wallet in another coordinator's format. The Coldcard file is the same setup
+    /// file as on the Transactions route, so saving it records the export the same way.
+    pub fn export_wallet(&mut self, ctx: &mut EventCtx, format: ExportFormat) {
+        let kind = match format {
+            ExportFormat::Coldcard => FileExport::RegistrationFile,
+            _ => FileExport::WalletFile,
+        };
+        match export::export(&self.wallet, format) {
+            Ok(contents) => {
+                self.export_status = "".into();
+                self.save_file(ctx, kind, contents);
+            }
+            Err(err) => self.export_status = err.to_string().into(),
+        }
+    }
+
+    pub fn open_backup(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        data.pending_import = Some(FileImport::Backup);
+        ctx.submit_command(commands::SHOW_OPEN_PANEL.with(FileDialogOptions::new()));
+    }
+
+    /// Adds the wallet from a backup file and rescans it, keeping its name unless a new one
+    /// was typed in.
+    fn restore_backup(&mut self, contents: &[u8]) {
+        let backup = WalletBackup::decrypt(contents, &self.backup_password);
+        self.backup_password.clear();
+        match backup.and_then(|backup| backup.restore()) {
+            Ok(wallet) => {
+                if self.add_wallet(wallet) {
+                    self.sync_balance();
+                }
+            }
+            Err(err) => self.wallets_status = err.to_string().into(),
+        }
+    }
+
+    pub fn open_wallet_file(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        data.pending_import = Some(FileImport::WalletFile);
+        ctx.submit_command(commands::SHOW_OPEN_PANEL.with(FileDialogOptions::new()));
+    }
+
+    /// Adds a wallet exported from Bitcoin Core, Specter, Sparrow or Coldcard and reports which
+    /// of its signers are plugged in.
+    fn import_wallet_file(&mut self, contents: &str) {
+        let wallet = match import::import(contents, self.wallet.network()) {
+            Ok(wallet) => wallet,
+            Err(err) => {
+                self.wallets_status = err.to_string().into();
+                return;
+            }
+        };
+        let connected = wallet.connected_signers(&self.devices).len();
+        let total = wallet.signer_fingerprints.len();
+        if self.add_wallet(wallet) {
+            self.registration_status = format!("{} of {} signers connected", connected, total).into();
+            self.sync_balance();
+        }
+    }
+
+    /// Names a newly created wallet, saves it to the registry and switches to it. Returns whether
+    /// it was added.
+    fn add_wallet(&mut self, wallet: SlapsWallet) -> bool {
+        let name = match (self.new_wallet_name.trim(), &self.registry) {
+            ("", _) if !wallet.name.is_empty() => wallet.name.to_string(),
+            ("", Some(registry)) => registry.next_name(),
+            (name, _) => name.to_string(),
+        };
+        let wallet = wallet.with_name(&name);
+        if !self.update_registry(|registry| registry.add(wallet.to_entry())) {
+            return false;
+        }
+        self.wallet_names.push_back(name.into());
+        self.new_wallet_name.clear();
+        self.wallets_status = "".into();
+        self.switch_to(wallet);
+        self.remember_wallet();
+        true
+    }
+
+    /// Writes changes to the open wallet, like device registrations, back to the registry.
+    fn save_wallet(&mut self) {
+        let entry = self.wallet.to_entry();
+        self.update_registry(|registry| registry.update(entry));
+    }
+
+    /// Changes the unlocked registry, showing any error. Returns whether it succeeded.
+    fn update_registry(
+        &mut self,
+        f: impl FnOnce(&mut WalletRegistry) -> Result<(), RegistryError>,
+    ) -> bool {
+        let registry = match self.registry.as_mut() {
+            Some(registry) => Arc::make_mut(registry),
+            None => {
+                self.wallets_status = "Wallets are locked".into();
+                return false;
+            }
+        };
+        match f(registry) {
+            Ok(()) => true,
+            Err(err) => {
+                self.wallets_status = err.to_string().into();
+                false
+            }
+        }
+    }
+
+    pub fn open_wallet(&mut self, name: &str) {
+        let entry = self.registry.as_ref().and_then(|registry| registry.get(name));
+        let wallet = match entry.map(SlapsWallet::from_entry) {
+            Some(Ok(wallet)) => wallet,
+            Some(Err(err)) => {
+                self.wallets_status = format!("Couldn't open {}: {}", name, err).into();
+                return;
+            }
+            None => return,
+        };
+        self.wallets_status = "".into();
+        self.switch_to(wallet);
+        self.remember_wallet();
+    }
+
+    /// Opens the current wallet again on the next start.
+    fn remember_wallet(&mut self) {
+        let name = self.wallet.name.clone();
+        self.update_registry(|registry| registry.set_last_opened(&name));
+    }
+
+    fn switch_to(&mut self, wallet: SlapsWallet) {
+        self.wallet = Arc::new(wallet);
+        self.address = "".into();
+        self.address_index = None;
+        self.address_verification = "".into();
+        self.address_label.clear();
+        self.transactions.clear();
+        self.utxos.clear();
+        self.history_status = "".into();
+        self.balance = "0 satoshis".into();
+        self.registration_status = "".into();
+        self.export_status = "".into();
+        self.send_status = "".into();
+        self.send_note = "".into();
+        self.payment_request = "".into();
+        self.payment_request_status = "".into();
+        self.psbt_frames.clear();
+        self.unsigned_psbt = None;
+        self.signed_psbt = None;
+        self.scanned_frames.clear();
+        self.psbt_qr_status = "".into();
+        self.active_route = Route::Transactions;
+        self.show_contacts();
+    }
+
+    /// Lists the saved contacts on the current wallet's network.
+    fn show_contacts(&mut self) {
+        let network = self.wallet.network();
+        self.contacts = self
+            .registry
+            .iter()
+            .flat_map(|registry| registry.contacts())
+            .filter(|contact| contact.is_on(network))
+            .map(|contact| UIContact {
+                name: contact.name.as_str().into(),
+                address: contact.address.as_str().into(),
+                notes: contact.notes.as_str().into(),
+            })
+            .collect();
+    }
+
+    pub fn save_contact(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        let contact = match Contact::new(
+            &data.contact_name,
+            &data.contact_address,
+            data.wallet.network(),
+            &data.contact_notes,
+        ) {
+            Ok(contact) => contact,
+            Err(err) => {
+                data.address_book_status = err.to_string().into();
+                return;
+            }
+        };
+        let added = match data.registry.as_mut() {
+            Some(registry) => Arc::make_mut(registry).add_contact(contact),
+            None => return,
+        };
+        match added {
+            Ok(()) => {
+                data.contact_name.clear();
+                data.contact_address.clear();
+                data.contact_notes.clear();
+                data.address_book_status = "".into();
+                data.show_contacts();
+            }
+            Err(err) => data.address_book_status = err.to_string().into(),
+        }
+    }
+
+    pub fn delete_contact(&mut self, name: &str) {
+        let removed = match self.registry.as_mut() {
+            Some(registry) => Arc::make_mut(registry).remove_contact(name),
+            None => return,
+        };
+        match removed {
+            Ok(()) => self.show_contacts(),
+            Err(err) => self.address_book_status = err.to_string().into(),
+        }
+    }
+
+    /// Fills in the send form with a saved recipient.
+    pub fn select_contact(&mut self, address: &str) {
+        self.fill_send_form(address);
+        self.active_route = Route::Send;
+    }
+
+    pub fn display_wallet_name(data: &Self, _env: &Env) -> String {
+        format!("Wallet: {}", data.wallet.name)
     }
 
     pub fn print_descriptors(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
@@ -130,18 +1348,42 @@ impl AppState {
         data.active_route = Route::Receive;
     }
 
+    pub fn go_to_setup_route(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        data.active_route = Route::Setup;
+    }
+
+    pub fn go_to_history_route(ctx: &mut EventCtx, data: &mut Self, env: &Env) {
+        data.active_route = Route::History;
+        Self::refresh_history(ctx, data, env);
+    }
+
+    pub fn go_to_address_book_route(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        data.show_contacts();
+        data.active_route = Route::AddressBook;
+    }
+
     pub fn go_to_transactions_route(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
         data.active_route = Route::Transactions;
     }
 
-    pub fn create_wallet_from_device(&mut self, device: Arc<SlapsDevice>) {
-        self.wallet = Arc::new(SlapsWallet::new_from_hw_wallet(&device.clone()));
-        self.active_route = Route::Transactions;
+    pub fn create_wallet_from_device(&mut self, device: Arc<SlapsDevice>, account: u32) {
+        match SlapsWallet::new_from_hw_wallet(&device, self.wallet_type.script_type(), account) {
+            Ok(wallet) => {
+                self.setup_status = "".into();
+                self.add_wallet(wallet);
+            }
+            Err(err) => self.setup_status = err.to_string().into(),
+        }
     }
 
     pub fn get_balance(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
-        let core = data.wallet.clone();
-        let sink = data.event_sink.clone();
+        data.sync_balance();
+    }
+
+    /// Syncs the wallet from scratch in the background and shows the balance when it's done.
+    fn sync_balance(&self) {
+        let core = self.wallet.clone();
+        let sink = self.event_sink.clone();
         task::spawn(async move {
                 let balance = core.get_balance();
                 sink.submit_command(selectors::UPDATE_BALANCE, balance, Target::Auto)
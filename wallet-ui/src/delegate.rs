@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use druid::{AppDelegate, Command, DelegateCtx, Env, Handled, Selector, Target};
+use druid::{commands, AppDelegate, Command, DelegateCtx, Env, Handled, Selector, Target};
 
 use crate::data::AppState;
 use crate::selectors::*;
@@ -16,14 +16,56 @@ impl AppDelegate<AppState> for Delegate {
         data: &mut AppState,
         _env: &Env,
     ) -> Handled {
-        if let Some(address) = cmd.get(UPDATE_ADDRESS) {
-            data.address = address.clone().into();
+        if let Some((address, index)) = cmd.get(UPDATE_ADDRESS) {
+            data.set_address(address, *index);
             Handled::Yes 
+        } else if let Some(status) = cmd.get(UPDATE_ADDRESS_VERIFICATION) {
+            data.address_verification = status.clone().into();
+            Handled::Yes
         } else if let Some(balance) = cmd.get(UPDATE_BALANCE) {
             data.set_balance(*balance);
             Handled::Yes
-        } else if let Some(device) = cmd.get(CREATE_WALLET) {
-            data.create_wallet_from_device(device.clone());
+        } else if let Some(status) = cmd.get(UPDATE_SETUP_STATUS) {
+            data.setup_status = status.clone().into();
+            Handled::Yes
+        } else if let Some((device, account)) = cmd.get(CREATE_WALLET) {
+            data.create_wallet_from_device(device.clone(), *account);
+            Handled::Yes
+        } else if let Some((device, account)) = cmd.get(ADD_MULTISIG_DEVICE) {
+            data.add_multisig_device(device.clone(), *account);
+            Handled::Yes
+        } else if let Some(history) = cmd.get(UPDATE_HISTORY) {
+            data.set_history(history);
+            Handled::Yes
+        } else if let Some(result) = cmd.get(UPDATE_SIGNED_TX) {
+            data.set_signed_tx(result);
+            Handled::Yes
+        } else if let Some(result) = cmd.get(UPDATE_PSBT_QR) {
+            data.set_psbt_qr(result);
+            Handled::Yes
+        } else if let Some(results) = cmd.get(UPDATE_REGISTRATIONS) {
+            data.set_registrations(results);
+            Handled::Yes
+        } else if let Some((label_type, reference, label)) = cmd.get(SET_LABEL) {
+            data.set_label(*label_type, reference, label);
+            Handled::Yes
+        } else if let Some(address) = cmd.get(SELECT_CONTACT) {
+            data.select_contact(address);
+            Handled::Yes
+        } else if let Some(name) = cmd.get(DELETE_CONTACT) {
+            data.delete_contact(name);
+            Handled::Yes
+        } else if let Some(registry) = cmd.get(UNLOCKED) {
+            data.set_unlocked(registry);
+            Handled::Yes
+        } else if let Some(name) = cmd.get(OPEN_WALLET) {
+            data.open_wallet(name);
+            Handled::Yes
+        } else if let Some(file_info) = cmd.get(commands::SAVE_FILE_AS) {
+            data.write_pending_export(file_info.path());
+            Handled::Yes
+        } else if let Some(file_info) = cmd.get(commands::OPEN_FILE) {
+            data.read_pending_import(file_info.path());
             Handled::Yes
         } else {
             println!("cmd forwarded: {:?}", cmd);
@@ -1,4 +1,7 @@
+use std::path::PathBuf;
+
 use druid::{AppLauncher, WindowDesc};
+use wallet_core::default_registry_path;
 
 mod data;
 use data::AppState;
@@ -10,6 +13,8 @@ mod selectors;
 
 mod delegate;
 
+mod qr;
+
 pub fn main() {
     let main_window = WindowDesc::new(build_ui)
         .title("Slaps Roof Of Wallet")
@@ -19,7 +24,10 @@ pub fn main() {
 
     let sink = app.get_external_handle();
 
-    let initial_state = AppState::new(sink);
+    let registry_path = default_registry_path().unwrap_or_else(|| PathBuf::from("slaps-wallets.json"));
+
+    // Starts on the unlock screen, which opens the last used wallet
+    let initial_state = AppState::new(sink, registry_path);
 
     let delegate = delegate::Delegate { }; 
     
@@ -0,0 +1,198 @@
+use std::time::Duration;
+
+use druid::{
+    im::Vector, kurbo::Rect, ArcStr, BoxConstraints, Color, Data, Env, Event, EventCtx, LayoutCtx,
+    LifeCycle, LifeCycleCtx, PaintCtx, RenderContext, Size, TimerToken, UpdateCtx, Widget,
+};
+use qrcode::{Color as Module, QrCode};
+
+/// Blank modules around the code, the minimum scanners expect.
+const QUIET_ZONE: usize = 4;
+
+/// How long each frame of an animated QR code is shown.
+const FRAME_INTERVAL: Duration = Duration::from_millis(300);
+
+/// An encoded QR code.
+#[derive(Default)]
+struct QrImage {
+    /// Modules row by row, `true` for dark
+    modules: Vec<bool>,
+    width: usize,
+}
+
+impl QrImage {
+    fn new(contents: &str) -> Self {
+        match QrCode::new(contents.as_bytes()) {
+            Ok(code) if !contents.is_empty() => Self {
+                width: code.width(),
+                modules: code.to_colors().into_iter().map(|m| m == Module::Dark).collect(),
+            },
+            _ => Self::default(),
+        }
+    }
+
+    fn size(&self, module_size: f64) -> f64 {
+        (self.width + 2 * QUIET_ZONE) as f64 * module_size
+    }
+
+    fn paint(&self, ctx: &mut PaintCtx, module_size: f64) {
+        if self.width == 0 {
+            return;
+        }
+        // Shrink to fit if the zoom asks for more room than we were given
+        let size = ctx.size();
+        let module_size = module_size.min(size.width.min(size.height) / self.size(1.0));
+
+        let side = self.size(module_size);
+        ctx.fill(Rect::new(0., 0., side, side), &Color::WHITE);
+        for (index, dark) in self.modules.iter().enumerate() {
+            if !dark {
+                continue;
+            }
+            let x = (index % self.width + QUIET_ZONE) as f64 * module_size;
+            let y = (index / self.width + QUIET_ZONE) as f64 * module_size;
+            ctx.fill(Rect::new(x, y, x + module_size, y + module_size), &Color::BLACK);
+        }
+    }
+}
+
+fn layout(image: Option<&QrImage>, bc: &BoxConstraints, module_size: f64) -> Size {
+    match image {
+        Some(image) if image.width > 0 => {
+            let side = image.size(module_size);
+            bc.constrain(Size::new(side, side))
+        }
+        _ => bc.min(),
+    }
+}
+
+/// Draws `contents(data)` as a QR code, `module_size(data)` pixels per module.
+pub struct Qr<T> {
+    contents: Box<dyn Fn(&T) -> String>,
+    module_size: Box<dyn Fn(&T) -> f64>,
+    encoded: String,
+    image: QrImage,
+}
+
+impl<T: Data> Qr<T> {
+    pub fn new(
+        contents: impl Fn(&T) -> String + 'static,
+        module_size: impl Fn(&T) -> f64 + 'static,
+    ) -> Self {
+        Self {
+            contents: Box::new(contents),
+            module_size: Box::new(module_size),
+            encoded: String::new(),
+            image: QrImage::default(),
+        }
+    }
+
+    fn encode(&mut self, data: &T) {
+        let contents = (self.contents)(data);
+        if contents != self.encoded {
+            self.image = QrImage::new(&contents);
+            self.encoded = contents;
+        }
+    }
+}
+
+impl<T: Data> Widget<T> for Qr<T> {
+    fn event(&mut self, _ctx: &mut EventCtx, _event: &Event, _data: &mut T, _env: &Env) {}
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.encode(data);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &T, data: &T, _env: &Env) {
+        if !old_data.same(data) {
+            self.encode(data);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &T, _env: &Env) -> Size {
+        layout(Some(&self.image), bc, (self.module_size)(data))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, _env: &Env) {
+        self.image.paint(ctx, (self.module_size)(data));
+    }
+}
+
+/// Cycles through `frames(data)` as QR codes, for payloads too large for one code like UR
+/// and BBQr encoded PSBTs.
+pub struct AnimatedQr<T> {
+    frames: Box<dyn Fn(&T) -> Vector<ArcStr>>,
+    module_size: Box<dyn Fn(&T) -> f64>,
+    encoded: Vector<ArcStr>,
+    images: Vec<QrImage>,
+    current: usize,
+    timer: TimerToken,
+}
+
+impl<T: Data> AnimatedQr<T> {
+    pub fn new(
+        frames: impl Fn(&T) -> Vector<ArcStr> + 'static,
+        module_size: impl Fn(&T) -> f64 + 'static,
+    ) -> Self {
+        Self {
+            frames: Box::new(frames),
+            module_size: Box::new(module_size),
+            encoded: Vector::new(),
+            images: vec![],
+            current: 0,
+            timer: TimerToken::INVALID,
+        }
+    }
+
+    fn encode(&mut self, data: &T) {
+        let frames = (self.frames)(data);
+        if frames != self.encoded {
+            self.images = frames.iter().map(|frame| QrImage::new(frame)).collect();
+            self.encoded = frames;
+            self.current = 0;
+        }
+    }
+}
+
+impl<T: Data> Widget<T> for AnimatedQr<T> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut T, _env: &Env) {
+        if let Event::Timer(token) = event {
+            if *token == self.timer {
+                if !self.images.is_empty() {
+                    self.current = (self.current + 1) % self.images.len();
+                    ctx.request_paint();
+                }
+                self.timer = ctx.request_timer(FRAME_INTERVAL);
+            }
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.encode(data);
+            self.timer = ctx.request_timer(FRAME_INTERVAL);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &T, data: &T, _env: &Env) {
+        if !old_data.same(data) {
+            self.encode(data);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &T, _env: &Env) -> Size {
+        // Frames of one payload can differ in version, so make room for the largest
+        let largest = self.images.iter().max_by_key(|image| image.width);
+        layout(largest, bc, (self.module_size)(data))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, _env: &Env) {
+        if let Some(image) = self.images.get(self.current) {
+            image.paint(ctx, (self.module_size)(data));
+        }
+    }
+}
@@ -1,10 +1,23 @@
 use std::sync::Arc;
 
-use druid::Selector;
-use wallet_core::SlapsDevice;
+use druid::{ArcStr, Selector};
+use wallet_core::psbt::PartiallySignedTransaction;
+use wallet_core::{labels::LabelType, HistoryItem, RegistrationStatus, SlapsDevice, UtxoItem, WalletRegistry};
 
 use crate::data::UIDevice;
 
-pub const UPDATE_ADDRESS: Selector<String> = Selector::new("slaps.update-address"); 
+pub const UPDATE_ADDRESS: Selector<(String, u32)> = Selector::new("slaps.update-address"); 
+pub const UPDATE_ADDRESS_VERIFICATION: Selector<String> = Selector::new("slaps.update-address-verification"); 
 pub const UPDATE_BALANCE: Selector<u64> = Selector::new("slaps.update-balance"); 
-pub const CREATE_WALLET: Selector<Arc<SlapsDevice>> = Selector::new("slaps.create-wallet"); 
+pub const UPDATE_SETUP_STATUS: Selector<String> = Selector::new("slaps.update-setup-status");
+pub const CREATE_WALLET: Selector<(Arc<SlapsDevice>, u32)> = Selector::new("slaps.create-wallet"); 
+pub const ADD_MULTISIG_DEVICE: Selector<(Arc<SlapsDevice>, u32)> = Selector::new("slaps.add-multisig-device"); 
+pub const UNLOCKED: Selector<Result<Arc<WalletRegistry>, String>> = Selector::new("slaps.unlocked");
+pub const OPEN_WALLET: Selector<ArcStr> = Selector::new("slaps.open-wallet");
+pub const UPDATE_HISTORY: Selector<Result<(Vec<HistoryItem>, Vec<UtxoItem>), String>> = Selector::new("slaps.update-history");
+pub const UPDATE_SIGNED_TX: Selector<Result<(Arc<PartiallySignedTransaction>, bool), String>> = Selector::new("slaps.update-signed-tx");
+pub const UPDATE_REGISTRATIONS: Selector<Vec<(SlapsDevice, Result<(ArcStr, RegistrationStatus), String>)>> = Selector::new("slaps.update-registrations");
+pub const UPDATE_PSBT_QR: Selector<Result<(Arc<PartiallySignedTransaction>, Vec<String>), String>> = Selector::new("slaps.update-psbt-qr");
+pub const SET_LABEL: Selector<(LabelType, ArcStr, String)> = Selector::new("slaps.set-label");
+pub const SELECT_CONTACT: Selector<ArcStr> = Selector::new("slaps.select-contact");
+pub const DELETE_CONTACT: Selector<ArcStr> = Selector::new("slaps.delete-contact");
@@ -2,25 +2,33 @@ use std::sync::Arc;
 
 use druid::{
     im::Vector,
-    widget::{Button, Flex, Label, List, TextBox, ViewSwitcher},
+    widget::{Button, Checkbox, Either, Flex, Label, List, RadioGroup, SizedBox, Stepper, TextBox, ViewSwitcher},
     ArcStr, Widget, WidgetExt,
 };
-use wallet_core::SlapsDevice;
+use wallet_core::{export::ExportFormat, SlapsDevice};
 
 use crate::data::*;
+use crate::qr::{AnimatedQr, Qr};
+use crate::selectors;
 
 fn single_device() -> impl Widget<UIDevice> {
     let model = Label::new(UIDevice::display_model);
     let fingerprint = Label::new(UIDevice::display_fingerprint);
     let path = Label::new(UIDevice::display_path);
+    let account = Flex::row()
+        .with_child(Label::new(UIDevice::display_account))
+        .with_child(Stepper::new().with_range(0.0, 100.0).with_step(1.0).lens(UIDevice::account));
     let print_xpub_button = Button::new("Print Xpub").on_click(UIDevice::print_xpub);
     let create_wallet_from_device = Button::new("Create wallet").on_click(UIDevice::create_wallet);
+    let add_to_multisig = Button::new("Add to multisig").on_click(UIDevice::add_to_multisig);
     Flex::column()
         .with_child(model)
         .with_child(fingerprint)
         .with_child(path)
+        .with_child(account)
         .with_child(print_xpub_button)
         .with_child(create_wallet_from_device)
+        .with_child(add_to_multisig)
 }
 
 fn devices() -> impl Widget<Vector<UIDevice>> {
@@ -31,52 +39,416 @@ fn devices() -> impl Widget<Vector<UIDevice>> {
     Flex::column().with_child(header).with_child(devices_list)
 }
 
+fn wallet_picker() -> impl Widget<AppState> {
+    let header = Label::new("Wallets").with_text_size(20.);
+
+    let wallets = List::new(|| {
+        Flex::row()
+            .with_child(Label::raw())
+            .with_child(Button::new("Open").on_click(|ctx, name: &mut ArcStr, _env| {
+                ctx.submit_command(selectors::OPEN_WALLET.with(name.clone()));
+            }))
+    })
+    .lens(AppState::wallet_names);
+
+    let new_wallet_name = TextBox::new()
+        .with_placeholder("Name for the next wallet")
+        .lens(AppState::new_wallet_name);
+
+    let backup_password = TextBox::new()
+        .with_placeholder("Backup password")
+        .lens(AppState::backup_password);
+    let restore_button = Button::new("Restore from backup").on_click(AppState::open_backup);
+
+    let import_button =
+        Button::new("Import Core, Sparrow or Coldcard file").on_click(AppState::open_wallet_file);
+
+    let status = Label::raw().lens(AppState::wallets_status);
+
+    Flex::column()
+        .with_child(header)
+        .with_child(wallets)
+        .with_child(new_wallet_name)
+        .with_child(backup_password)
+        .with_child(restore_button)
+        .with_child(import_button)
+        .with_child(status)
+}
+
+fn multisig_setup() -> impl Widget<AppState> {
+    let header = Label::new("Multisig").with_text_size(20.);
+
+    let threshold = Flex::row()
+        .with_child(Label::new("Signatures required (k)"))
+        .with_child(Stepper::new().with_range(1.0, 15.0).with_step(1.0).lens(AppState::multisig_threshold));
+
+    let total = Flex::row()
+        .with_child(Label::new("Total keys (n)"))
+        .with_child(Stepper::new().with_range(1.0, 15.0).with_step(1.0).lens(AppState::multisig_total));
+
+    let summary = Label::new(AppState::display_multisig_setup);
+
+    let keys = List::new(|| Label::raw()).lens(AppState::multisig_keys);
+
+    let key_input = TextBox::new()
+        .with_placeholder("[fingerprint/48'/1'/0'/2']tpub...")
+        .lens(AppState::multisig_key_input);
+
+    let add_key_button = Button::new("Add pasted xpub").on_click(AppState::add_pasted_multisig_key);
+
+    let clear_button = Button::new("Clear keys").on_click(AppState::clear_multisig_keys);
+
+    let create_button = Button::new("Create multisig wallet").on_click(AppState::create_multisig_wallet);
+
+    let status = Label::raw().lens(AppState::multisig_status);
+
+    Flex::column()
+        .with_child(header)
+        .with_child(threshold)
+        .with_child(total)
+        .with_child(summary)
+        .with_child(keys)
+        .with_child(key_input)
+        .with_child(add_key_button)
+        .with_child(clear_button)
+        .with_child(create_button)
+        .with_child(status)
+}
+
+fn bsms_setup() -> impl Widget<AppState> {
+    let header = Label::new("BSMS coordinator").with_text_size(20.);
+
+    let start_button = Button::new("Start BSMS session").on_click(AppState::start_bsms_session);
+
+    let import_button = Button::new("Import signer record").on_click(AppState::open_bsms_record);
+
+    let export_button =
+        Button::new("Export descriptor record for cosigners").on_click(AppState::export_bsms_descriptor);
+
+    let status = Label::raw().lens(AppState::bsms_status);
+
+    Flex::column()
+        .with_child(header)
+        .with_child(start_button)
+        .with_child(import_button)
+        .with_child(export_button)
+        .with_child(status)
+}
+
+fn watch_only_setup() -> impl Widget<AppState> {
+    let header = Label::new("Watch-only").with_text_size(20.);
+
+    let descriptor = TextBox::new()
+        .with_placeholder("Descriptor")
+        .lens(AppState::watch_only_descriptor);
+
+    let change_descriptor = TextBox::new()
+        .with_placeholder("Change descriptor")
+        .lens(AppState::watch_only_change_descriptor);
+
+    let descriptors_button =
+        Button::new("Watch descriptors").on_click(AppState::create_watch_only_from_descriptors);
+
+    let xpub = TextBox::new()
+        .with_placeholder("[fingerprint/84'/1'/0']vpub...")
+        .lens(AppState::watch_only_xpub);
+
+    let xpub_button = Button::new("Watch xpub").on_click(AppState::create_watch_only_from_xpub);
+
+    let status = Label::raw().lens(AppState::watch_only_status);
+
+    Flex::column()
+        .with_child(header)
+        .with_child(descriptor)
+        .with_child(change_descriptor)
+        .with_child(descriptors_button)
+        .with_child(xpub)
+        .with_child(xpub_button)
+        .with_child(status)
+}
+
 fn setup() -> impl Widget<AppState> {
     let header = Label::new("Setup").with_text_size(28.);
     let refresh_devices_button = Button::new("Refresh devices").on_click(AppState::refresh_devices);
+    let wallet_type = RadioGroup::new(vec![
+        ("Legacy (pkh, BIP44)", WalletType::Legacy),
+        ("Nested segwit (sh-wpkh, BIP49)", WalletType::NestedSegwit),
+        ("Native segwit (wpkh, BIP84)", WalletType::NativeSegwit),
+    ])
+    .lens(AppState::wallet_type);
     let device_list = devices().lens(AppState::ui_device_list);
+    let setup_status = Label::raw().lens(AppState::setup_status);
 
     Flex::column()
         .with_child(header)
+        .with_child(wallet_picker())
         .with_child(refresh_devices_button)
+        .with_child(wallet_type)
         .with_child(device_list)
+        .with_child(setup_status)
+        .with_child(multisig_setup())
+        .with_child(bsms_setup())
+        .with_child(watch_only_setup())
+}
+
+fn unlock() -> impl Widget<AppState> {
+    let header = Label::new("Unlock").with_text_size(28.);
+    let prompt = Label::new(AppState::display_unlock_prompt);
+
+    let password = TextBox::new().with_placeholder("Password").lens(AppState::password);
+
+    let confirm = Either::new(
+        |data: &AppState, _env| data.new_store || data.unencrypted_store,
+        TextBox::new()
+            .with_placeholder("Repeat password")
+            .lens(AppState::password_confirm),
+        SizedBox::empty(),
+    );
+
+    let confirm_migration = Either::new(
+        |data: &AppState, _env| data.unencrypted_store,
+        Checkbox::new("Encrypt my unencrypted wallet config with this password")
+            .lens(AppState::confirm_migration),
+        SizedBox::empty(),
+    );
+
+    let unlock_button = Button::new("Unlock").on_click(AppState::unlock);
+
+    let status = Label::raw().lens(AppState::unlock_status);
+
+    Flex::column()
+        .with_child(header)
+        .with_child(prompt)
+        .with_child(password)
+        .with_child(confirm)
+        .with_child(confirm_migration)
+        .with_child(unlock_button)
+        .with_child(status)
+}
+
+fn export_buttons() -> impl Widget<AppState> {
+    let export_button = |label, format| {
+        Button::new(label).on_click(move |ctx, data: &mut AppState, _env| data.export_wallet(ctx, format))
+    };
+
+    Flex::column()
+        .with_child(Label::new("Export to"))
+        .with_child(
+            Flex::row()
+                .with_child(export_button("Bitcoin Core", ExportFormat::BitcoinCore))
+                .with_child(export_button("Sparrow/Specter", ExportFormat::Specter))
+                .with_child(export_button("Electrum", ExportFormat::Electrum))
+                .with_child(export_button("Coldcard", ExportFormat::Coldcard)),
+        )
+        .with_child(Label::raw().lens(AppState::export_status))
 }
 
 fn transactions() -> impl Widget<AppState> {
     let header = Label::new("Transactions").with_text_size(28.);
+    let wallet_name = Label::new(AppState::display_wallet_name);
+    let switch_wallet_button = Button::new("Switch wallet").on_click(AppState::go_to_setup_route);
+    let lock_button = Button::new("Lock").on_click(AppState::lock);
     let refresh_balance_button = Button::new("Refresh balance").on_click(AppState::get_balance);
     let balance_display = Label::raw().lens(AppState::balance);
     let print_descriptors = Button::new("Print descriptors").on_click(AppState::print_descriptors);
+    let backup_password = TextBox::new()
+        .with_placeholder("Backup password")
+        .lens(AppState::backup_password);
+    let export_backup_button = Button::new("Export encrypted backup").on_click(AppState::export_backup);
+
+    let registrations = Label::new(AppState::display_registrations);
+    let register_button = Button::new("Register multisig on devices").on_click(AppState::register_multisig);
+    let export_registration_button =
+        Button::new("Export Coldcard setup file").on_click(AppState::export_registration_file);
+    let registration_status = Label::raw().lens(AppState::registration_status);
 
     let send_button = Button::new("Send").on_click(AppState::go_to_send_route);
     let receive_button = Button::new("Receive").on_click(AppState::go_to_receive_route);
+    let history_button = Button::new("History").on_click(AppState::go_to_history_route);
 
     Flex::column()
         .with_child(header)
+        .with_child(wallet_name)
+        .with_child(switch_wallet_button)
+        .with_child(lock_button)
         .with_child(balance_display)
         .with_child(refresh_balance_button)
         .with_child(print_descriptors)
+        .with_child(backup_password)
+        .with_child(export_backup_button)
+        .with_child(export_buttons())
+        .with_child(registrations)
+        .with_child(register_button)
+        .with_child(export_registration_button)
+        .with_child(registration_status)
         .with_child(send_button)
         .with_child(receive_button)
+        .with_child(history_button)
 }
 
 fn send() -> impl Widget<AppState> {
     let header = Label::new("Send").with_text_size(28.);
 
-    let to_address = TextBox::new().lens(AppState::send_to_address);
+    let to_address = TextBox::new()
+        .with_placeholder("Address")
+        .lens(AppState::send_to_address);
+
+    let address_check = Label::new(AppState::display_send_address);
+
+    let amount = Flex::row()
+        .with_child(TextBox::new().with_placeholder("Amount (sats)").lens(AppState::send_amount))
+        .with_child(Label::new(AppState::display_send_amount));
+
+    let note = Label::raw().lens(AppState::send_note);
 
     let paste_send_button =
-        Button::new("Paste send address").on_click(AppState::paste_send_address);
+        Button::new("Paste address or payment URI").on_click(AppState::paste_send_address);
+
+    let address_book_button =
+        Button::new("Choose from address book").on_click(AppState::go_to_address_book_route);
+
+    let create_tx_button = Either::new(
+        |data: &AppState, _env| data.send_address_valid(),
+        Button::new("Create transaction").on_click(AppState::create_tx),
+        Label::new("Enter a valid address to create a transaction"),
+    );
+
+    let send_status = Label::raw().lens(AppState::send_status);
+
+    let psbt_qr_format = RadioGroup::new(vec![("UR", QrFormat::Ur), ("BBQr", QrFormat::Bbqr)])
+        .lens(AppState::psbt_qr_format);
+
+    let show_psbt_qr_button = Either::new(
+        |data: &AppState, _env| data.send_address_valid(),
+        Button::new("Show PSBT for QR signer").on_click(AppState::show_psbt_qr),
+        SizedBox::empty(),
+    );
+
+    let psbt_qr = AnimatedQr::new(AppState::animated_psbt_frames, AppState::qr_module_size);
+
+    let import_signed_button =
+        Button::new("Open signed PSBT QR image").on_click(AppState::open_signed_psbt_qr);
 
-    let create_tx_button = Button::new("Create transaction").on_click(AppState::create_tx);
+    let psbt_qr_status = Label::raw().lens(AppState::psbt_qr_status);
+
+    let save_signed_button = Either::new(
+        |data: &AppState, _env| data.signed_psbt.is_some(),
+        Button::new("Save signed PSBT").on_click(AppState::export_signed_psbt),
+        SizedBox::empty(),
+    );
 
     let back_button = Button::new("Back").on_click(AppState::go_to_transactions_route);
 
     Flex::column()
         .with_child(header)
         .with_child(to_address)
+        .with_child(address_check)
+        .with_child(amount)
+        .with_child(note)
         .with_child(paste_send_button)
+        .with_child(address_book_button)
         .with_child(create_tx_button)
+        .with_child(send_status)
+        .with_child(psbt_qr_format)
+        .with_child(show_psbt_qr_button)
+        .with_child(psbt_qr)
+        .with_child(import_signed_button)
+        .with_child(psbt_qr_status)
+        .with_child(save_signed_button)
+        .with_child(back_button)
+}
+
+fn single_transaction() -> impl Widget<UITransaction> {
+    let summary = Label::raw().lens(UITransaction::summary);
+    let label = TextBox::new().with_placeholder("Label").lens(UITransaction::label);
+    let save_button = Button::new("Save label").on_click(UITransaction::save_label);
+
+    Flex::column()
+        .with_child(summary)
+        .with_child(Flex::row().with_child(label).with_child(save_button))
+}
+
+fn single_utxo() -> impl Widget<UIUtxo> {
+    let summary = Label::raw().lens(UIUtxo::summary);
+    let label = TextBox::new().with_placeholder("Label").lens(UIUtxo::label);
+    let save_button = Button::new("Save label").on_click(UIUtxo::save_label);
+
+    Flex::column()
+        .with_child(summary)
+        .with_child(Flex::row().with_child(label).with_child(save_button))
+}
+
+fn history() -> impl Widget<AppState> {
+    let header = Label::new("History").with_text_size(28.);
+
+    let refresh_button = Button::new("Refresh").on_click(AppState::refresh_history);
+
+    let transactions = List::new(single_transaction).lens(AppState::transactions);
+
+    let utxos = List::new(single_utxo).lens(AppState::utxos);
+
+    let import_button = Button::new("Import BIP329 labels").on_click(AppState::open_labels);
+
+    let export_button = Button::new("Export BIP329 labels").on_click(AppState::export_labels);
+
+    let status = Label::raw().lens(AppState::history_status);
+
+    let back_button = Button::new("Back").on_click(AppState::go_to_transactions_route);
+
+    Flex::column()
+        .with_child(header)
+        .with_child(refresh_button)
+        .with_child(status)
+        .with_child(Label::new("Transactions").with_text_size(20.))
+        .with_child(transactions)
+        .with_child(Label::new("Coins").with_text_size(20.))
+        .with_child(utxos)
+        .with_child(import_button)
+        .with_child(export_button)
+        .with_child(back_button)
+}
+
+fn single_contact() -> impl Widget<UIContact> {
+    let name = Label::raw().lens(UIContact::name);
+    let address = Label::raw().lens(UIContact::address);
+    let notes = Label::raw().lens(UIContact::notes);
+    let select_button = Button::new("Send to").on_click(UIContact::select);
+    let delete_button = Button::new("Delete").on_click(UIContact::delete);
+
+    Flex::column()
+        .with_child(name)
+        .with_child(address)
+        .with_child(notes)
+        .with_child(Flex::row().with_child(select_button).with_child(delete_button))
+}
+
+fn address_book() -> impl Widget<AppState> {
+    let header = Label::new("Address book").with_text_size(28.);
+
+    let contacts = List::new(single_contact).lens(AppState::contacts);
+
+    let name = TextBox::new().with_placeholder("Name").lens(AppState::contact_name);
+
+    let address = TextBox::new()
+        .with_placeholder("Address or bitcoin: URI")
+        .lens(AppState::contact_address);
+
+    let notes = TextBox::new().with_placeholder("Notes").lens(AppState::contact_notes);
+
+    let save_button = Button::new("Save contact").on_click(AppState::save_contact);
+
+    let status = Label::raw().lens(AppState::address_book_status);
+
+    let back_button = Button::new("Back").on_click(AppState::go_to_send_route);
+
+    Flex::column()
+        .with_child(header)
+        .with_child(contacts)
+        .with_child(name)
+        .with_child(address)
+        .with_child(notes)
+        .with_child(save_button)
+        .with_child(status)
         .with_child(back_button)
 }
 
@@ -89,6 +461,40 @@ fn receive() -> impl Widget<AppState> {
 
     let copy_button = Button::new("Copy address").on_click(AppState::copy_address);
 
+    let address_label = TextBox::new().with_placeholder("Label").lens(AppState::address_label);
+
+    let save_label_button = Button::new("Save label").on_click(AppState::save_address_label);
+
+    let verify_button = Button::new("Verify on device").on_click(AppState::verify_address);
+
+    let request_amount = TextBox::new()
+        .with_placeholder("Amount (sats, optional)")
+        .lens(AppState::receive_amount);
+
+    let request_label = TextBox::new()
+        .with_placeholder("Label (optional)")
+        .lens(AppState::receive_label);
+
+    let request_button = Button::new("Create payment request").on_click(AppState::create_payment_request);
+
+    let payment_request = Label::raw().lens(AppState::payment_request);
+
+    let payment_request_status = Label::raw().lens(AppState::payment_request_status);
+
+    let copy_request_button = Either::new(
+        |data: &AppState, _env| data.payment_request.starts_with("bitcoin:"),
+        Button::new("Copy payment request").on_click(AppState::copy_payment_request),
+        SizedBox::empty(),
+    );
+
+    let qr_code = Qr::new(AppState::qr_contents, AppState::qr_module_size);
+
+    let qr_zoom = Flex::row()
+        .with_child(Label::new(AppState::display_qr_zoom))
+        .with_child(Stepper::new().with_range(1.0, 12.0).with_step(1.0).lens(AppState::qr_zoom));
+
+    let verification_display = Label::raw().lens(AppState::address_verification);
+
     let back_button = Button::new("Back").on_click(AppState::go_to_transactions_route);
 
     Flex::column()
@@ -96,6 +502,17 @@ fn receive() -> impl Widget<AppState> {
         .with_child(address_button)
         .with_child(address_display)
         .with_child(copy_button)
+        .with_child(Flex::row().with_child(address_label).with_child(save_label_button))
+        .with_child(verify_button)
+        .with_child(verification_display)
+        .with_child(request_amount)
+        .with_child(request_label)
+        .with_child(request_button)
+        .with_child(payment_request)
+        .with_child(payment_request_status)
+        .with_child(copy_request_button)
+        .with_child(qr_code)
+        .with_child(qr_zoom)
         .with_child(back_button)
 }
 
@@ -103,10 +520,13 @@ pub fn build_ui() -> impl Widget<AppState> {
     ViewSwitcher::new(
         |data: &AppState, _env| data.active_route,
         |selector, _data, _env| match selector {
-            Route::Setup => setup().boxed(),
-            Route::Transactions => transactions().boxed(),
-            Route::Send => send().boxed(),
-            Route::Receive => receive().boxed(),
+            Route::Unlock => unlock().boxed(),
+            Route::Setup => setup().scroll().vertical().boxed(),
+            Route::Transactions => transactions().scroll().vertical().boxed(),
+            Route::Send => send().scroll().vertical().boxed(),
+            Route::Receive => receive().scroll().vertical().boxed(),
+            Route::History => history().scroll().vertical().boxed(),
+            Route::AddressBook => address_book().scroll().vertical().boxed(),
         },
     )
 }